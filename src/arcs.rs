@@ -0,0 +1,316 @@
+//! Arc diagrams for layers whose data are links, such as dependency parses
+use std::collections::HashMap;
+use yew::prelude::*;
+use crate::offsets::{char_len, char_slice};
+use crate::serialization::{TeangaError, TeangaResult};
+use crate::teanga::{Data, DataType, Document, Layer, LayerDesc};
+
+/// The width of a character of a word in a diagram
+const CHAR_WIDTH : usize = 8;
+/// The space between the words of a diagram
+const WORD_GAP : usize = 16;
+/// The height that each level of arcs adds to a diagram
+const LEVEL_HEIGHT : usize = 22;
+/// The height of the line of words under the arcs
+const TEXT_HEIGHT : usize = 20;
+
+#[derive(Debug, Clone, PartialEq)]
+/// An arc from a head to a dependent, as indexes into the words of a diagram.
+/// An arc whose head is its dependent marks the root.
+pub struct Arc {
+    pub head : usize,
+    pub dependent : usize,
+    pub label : Option<String>,
+    /// The height of the arc in levels, which is one more than the highest
+    /// arc under it. The root has level 0.
+    pub level : usize,
+    /// Whether the arc crosses another arc, that is, it is not projective
+    pub crossing : bool,
+}
+
+impl Arc {
+    /// The first and last word that the arc spans
+    fn span(&self) -> (usize, usize) {
+        (self.head.min(self.dependent), self.head.max(self.dependent))
+    }
+}
+
+/// An item of a layer, as the name of the layer and the index of the item
+pub type Item = (String, usize);
+
+#[derive(Debug, Clone, PartialEq)]
+/// A word of a diagram, which is an item of the layer that the links join
+pub struct Word {
+    pub text : String,
+    /// The offsets of the word in the character layer
+    pub start : usize,
+    pub end : usize,
+    pub item : Item,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The arcs of a link layer drawn over the words they join
+pub struct ArcDiagram {
+    pub layer : String,
+    /// The character layer that the words are in
+    pub on : String,
+    /// The words in text order
+    pub words : Vec<Word>,
+    /// The arcs, one for each annotation of the layer in order
+    pub arcs : Vec<Arc>,
+}
+
+/// Whether the data of a layer are links that can be drawn as arcs
+pub fn is_link_layer(desc : &LayerDesc) -> bool {
+    matches!(desc.data, Some(DataType::Link) | Some(DataType::TypedLink(_)))
+}
+
+/// The offsets of each item of a layer in the character layer it is on
+fn item_offsets<'b>(doc : &Document, name : &'b str, meta : &'b HashMap<String, LayerDesc>)
+    -> TeangaResult<(Vec<(usize, usize)>, &'b str)> {
+    match doc.content.get(name) {
+        Some(Layer::Characters(s)) => Ok(((0..char_len(s)).map(|i| (i, i + 1)).collect(), name)),
+        _ => {
+            let (annos, on) = doc.base_annos(name, meta)?;
+            Ok((annos.iter().map(|a| (a.start, a.end)).collect(), on))
+        }
+    }
+}
+
+/// The item that an item of a layer stands for. An item of a seq layer is the
+/// item at the same index of the layer it is on and an element is the item it
+/// is attached to, so words are the same item whichever layer names them.
+fn base_item(doc : &Document, meta : &HashMap<String, LayerDesc>, name : &str, index : usize) -> Item {
+    let (mut name, mut index) = (name, index);
+    while let Some(desc) = meta.get(name) {
+        index = match doc.content.get(name) {
+            Some(Layer::Seq(_)) => index,
+            Some(Layer::Element(data)) => match data.get(index) {
+                Some((i, _)) => *i,
+                None => break
+            },
+            Some(Layer::ElementNoData(data)) => match data.get(index) {
+                Some(i) => *i,
+                None => break
+            },
+            _ => break
+        };
+        name = &desc.on;
+    }
+    (name.to_string(), index)
+}
+
+/// Build the arc diagram of a link layer. Each annotation of the layer is a
+/// dependent and its link is the index of its head in the target of the layer,
+/// or in the layer it is on if it has no target. Words are identified by the
+/// item they stand for rather than by their offsets, as the words of a
+/// multi-word token share its offsets.
+pub fn link_arcs(doc : &Document, name : &str, meta : &HashMap<String, LayerDesc>) -> TeangaResult<ArcDiagram> {
+    let desc = meta.get(name).ok_or_else(|| TeangaError::MissingMeta(name.to_string()))?;
+    let target = desc.target.as_deref().unwrap_or(&desc.on);
+    let (annos, on) = doc.base_annos(name, meta)?;
+    let (heads, target_on) = item_offsets(doc, target, meta)?;
+    if on != target_on {
+        return Err(TeangaError::ModelError(format!("Layer {} links to {}, which is on a different text", name, target)));
+    }
+    let Some(Layer::Characters(text)) = doc.content.get(on) else {
+        return Err(TeangaError::MissingLayer(on.to_string()));
+    };
+    let mut offsets : HashMap<Item, (usize, usize)> = HashMap::new();
+    let mut links = Vec::new();
+    for (i, anno) in annos.iter().enumerate() {
+        let (head, label) = match &anno.data {
+            Some(Data::Link(j)) => (*j, None),
+            Some(Data::TypedLink(j, label)) => (*j, Some(label.clone())),
+            _ => return Err(TeangaError::ModelError(format!("Layer {} contains data that are not links", name)))
+        };
+        let head_offsets = *heads.get(head).ok_or_else(|| TeangaError::IndexOutOfRange(name.to_string(), i))?;
+        let head = base_item(doc, meta, target, head);
+        let dependent = base_item(doc, meta, name, anno.index);
+        offsets.insert(head.clone(), head_offsets);
+        offsets.insert(dependent.clone(), (anno.start, anno.end));
+        links.push((head, dependent, label));
+    }
+    let mut words : Vec<Word> = offsets.into_iter()
+        .map(|(item, (start, end))| Word {
+            text: char_slice(text, start, end).unwrap_or_default().to_string(),
+            start,
+            end,
+            item
+        })
+        .collect();
+    words.sort_by(|a, b| (a.start, a.end, &a.item).cmp(&(b.start, b.end, &b.item)));
+    let index : HashMap<&Item, usize> = words.iter().enumerate().map(|(k, word)| (&word.item, k)).collect();
+    let arcs = links.into_iter().map(|(head, dependent, label)| Arc {
+        head: index[&head],
+        dependent: index[&dependent],
+        label,
+        level: 0,
+        crossing: false
+    }).collect();
+    let mut diagram = ArcDiagram { layer: name.to_string(), on: on.to_string(), words, arcs };
+    diagram.place_arcs();
+    Ok(diagram)
+}
+
+impl ArcDiagram {
+    /// Set the level of each arc so that it is drawn above the arcs within it
+    /// and the shorter arcs it crosses, and find the arcs that cross another
+    fn place_arcs(&mut self) {
+        let mut order : Vec<usize> = (0..self.arcs.len()).filter(|k| self.arcs[*k].head != self.arcs[*k].dependent).collect();
+        order.sort_by_key(|k| { let (i, j) = self.arcs[*k].span(); j - i });
+        for (n, k) in order.iter().enumerate() {
+            let (i, j) = self.arcs[*k].span();
+            let mut level = 1;
+            let mut crossing = false;
+            for (m, l) in order.iter().enumerate() {
+                let (i2, j2) = self.arcs[*l].span();
+                let crosses = (i < i2 && i2 < j && j < j2) || (i2 < i && i < j2 && j2 < j);
+                if m < n && (crosses || (i <= i2 && j2 <= j)) {
+                    level = level.max(self.arcs[*l].level + 1);
+                }
+                crossing |= crosses;
+            }
+            self.arcs[*k].level = level;
+            self.arcs[*k].crossing = crossing;
+        }
+    }
+
+    /// Split the words into runs that no arc joins, as the ranges of their
+    /// words and the arcs within them
+    pub fn chunks(&self) -> Vec<(std::ops::Range<usize>, Vec<&Arc>)> {
+        let mut open = vec![0isize; self.words.len() + 1];
+        for arc in self.arcs.iter() {
+            let (i, j) = arc.span();
+            open[i] += 1;
+            open[j] -= 1;
+        }
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        let mut depth = 0;
+        for (k, d) in open.iter().enumerate().take(self.words.len()) {
+            depth += d;
+            if depth == 0 {
+                let arcs = self.arcs.iter().filter(|a| a.span().0 >= start && a.span().1 <= k).collect();
+                chunks.push((start..k + 1, arcs));
+                start = k + 1;
+            }
+        }
+        chunks
+    }
+}
+
+/// Draw a diagram as a row of SVG images, one for each run of joined words,
+/// in the colour of its layer
+pub fn render_arcs(diagram : &ArcDiagram, color : &str) -> Html {
+    diagram.chunks().into_iter().enumerate().map(|(c, (words, arcs))| {
+        let mut centers = HashMap::new();
+        let mut x = 0;
+        let mut labels = Vec::new();
+        for k in words {
+            let text = &diagram.words[k].text;
+            let width = char_len(text).max(1) * CHAR_WIDTH;
+            centers.insert(k, x + width / 2);
+            labels.push(html! {
+                <text x={(x + width / 2).to_string()} y={(TEXT_HEIGHT / 2).to_string()} text-anchor="middle"
+                    dominant-baseline="middle" font-family="monospace" font-size="13" fill="black">{ text.clone() }</text>
+            });
+            x += width + WORD_GAP;
+        }
+        let levels = arcs.iter().map(|a| a.level).max().unwrap_or(0) + 1;
+        let height = levels * LEVEL_HEIGHT + TEXT_HEIGHT;
+        let base = levels * LEVEL_HEIGHT;
+        let marker = format!("arrow-{}-{}", diagram.layer, c);
+        let marker_end = format!("url(#{})", marker);
+        let paths = arcs.iter().map(|arc| {
+            let to = centers[&arc.dependent];
+            let dash = if arc.crossing { "4 2" } else { "none" };
+            if arc.head == arc.dependent {
+                html! {
+                    <g>
+                        <line x1={to.to_string()} y1="4" x2={to.to_string()} y2={base.to_string()}
+                            stroke="currentColor" marker-end={marker_end.clone()}/>
+                        <text x={(to + 3).to_string()} y="10" font-size="10" fill="currentColor">{ arc.label.clone().unwrap_or_default() }</text>
+                    </g>
+                }
+            } else {
+                let from = centers[&arc.head];
+                // A cubic curve with both control points this high peaks at
+                // three quarters of their height
+                let top = base - arc.level * LEVEL_HEIGHT + 6;
+                let control = base as isize - ((base - top) * 4 / 3) as isize;
+                let path = format!("M {} {} C {} {} {} {} {} {}", from, base, from, control, to, control, to, base);
+                html! {
+                    <g>
+                        <path d={path} fill="none" stroke="currentColor" stroke-dasharray={dash}
+                            marker-end={marker_end.clone()}/>
+                        <text x={((from + to) / 2).to_string()} y={(top - 2).to_string()} text-anchor="middle"
+                            font-size="10" fill="currentColor">{ arc.label.clone().unwrap_or_default() }</text>
+                    </g>
+                }
+            }
+        }).collect::<Html>();
+        html! {
+            <svg class={classes!("inline-block", "me-4", format!("text-{}-900", color))}
+                width={x.to_string()} height={height.to_string()}>
+                <defs>
+                    <marker id={marker} viewBox="0 0 10 10" refX="10" refY="5" markerWidth="6" markerHeight="6" orient="auto">
+                        <path d="M 0 0 L 10 5 L 0 10 z" fill="currentColor"/>
+                    </marker>
+                </defs>
+                { paths }
+                <g transform={format!("translate(0 {})", base)}>{ labels }</g>
+            </svg>
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::read_corpus_from_json_string;
+
+    #[test]
+    fn test_link_arcs() {
+        // "A hearing is scheduled on the issue today" has a non-projective
+        // arc from "hearing" to "issue" that crosses the arc to "today"
+        let corpus = read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\"},
+\"dep\":{\"type\":\"seq\",\"on\":\"tokens\",\"data\":\"link\",\"values\":[\"det\",\"nsubj\",\"aux\",\"root\",\"case\",\"nmod\",\"obl\"],\"target\":\"tokens\"}},
+\"Kjco\":{\"text\":\"A hearing is scheduled on the issue today\",
+\"tokens\":[[0,1],[2,9],[10,12],[13,22],[23,25],[26,29],[30,35],[36,41]],
+\"dep\":[[1,\"det\"],[3,\"nsubj\"],[3,\"aux\"],[3,\"root\"],[6,\"case\"],[6,\"det\"],[1,\"nmod\"],[3,\"obl\"]]}}").unwrap();
+        let diagram = link_arcs(&corpus.documents[0].1, "dep", &corpus.meta).unwrap();
+        assert_eq!(diagram.on, "text");
+        assert_eq!(diagram.words.len(), 8);
+        assert_eq!(diagram.words[1], Word { text: "hearing".to_string(), start: 2, end: 9, item: ("tokens".to_string(), 1) });
+        let arcs : Vec<(usize, usize, &str, usize, bool)> = diagram.arcs.iter()
+            .map(|a| (a.head, a.dependent, a.label.as_deref().unwrap(), a.level, a.crossing)).collect();
+        assert_eq!(arcs, vec![
+            (1, 0, "det", 1, false),
+            (3, 1, "nsubj", 2, false),
+            (3, 2, "aux", 1, false),
+            (3, 3, "root", 0, false),
+            (6, 4, "case", 2, false),
+            (6, 5, "det", 1, false),
+            (1, 6, "nmod", 4, true),
+            (3, 7, "obl", 3, true)]);
+        let chunks = diagram.chunks();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].0, 0..8);
+    }
+
+    #[test]
+    fn test_chunks() {
+        let corpus = read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\"},
+\"dep\":{\"type\":\"seq\",\"on\":\"tokens\",\"data\":\"link\"}},
+\"Kjco\":{\"text\":\"Dia duit. Conas atá tú?\",\"tokens\":[[0,3],[4,8],[10,15],[16,19],[20,22]],
+\"dep\":[0,0,2,2,3]}}").unwrap();
+        let diagram = link_arcs(&corpus.documents[0].1, "dep", &corpus.meta).unwrap();
+        let chunks : Vec<(std::ops::Range<usize>, usize)> = diagram.chunks().into_iter()
+            .map(|(words, arcs)| (words, arcs.len())).collect();
+        assert_eq!(chunks, vec![(0..2, 2), (2..5, 3)]);
+        assert!(diagram.arcs.iter().all(|a| a.label.is_none()));
+    }
+}
@@ -0,0 +1,448 @@
+//! Conversion between brat standoff files and Teanga corpora
+//!
+//! Each document is a pair of a `.txt` file and an `.ann` file. Offsets in
+//! brat count Unicode code points, the same as the character indices of a
+//! Teanga characters layer. Files from tools that count UTF-16 code units or
+//! UTF-8 bytes are also read, when the text of an annotation shows which
+//! count gives its offsets.
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{Cursor, Write};
+use zip::write::{SimpleFileOptions, ZipWriter};
+use crate::teanga::{Corpus, Document, Layer, LayerDesc, LayerType, DataType, Data, TEXT_LAYER};
+use crate::serialization::{TeangaError, TeangaResult};
+use crate::offsets::{byte_to_char, char_slice, utf16_to_char};
+
+/// The names of the layers created when importing brat files, besides
+/// their text
+pub const ENTITIES_LAYER : &str = "entities";
+pub const RELATIONS_LAYER : &str = "relations";
+pub const ATTRIBUTES_LAYER : &str = "attributes";
+
+/// A document in brat format
+#[derive(Debug, Clone, PartialEq)]
+pub struct BratDocument {
+    pub id: String,
+    pub txt: String,
+    pub ann: String,
+}
+
+/// Pair up `.txt` and `.ann` files, given as file names and contents, into
+/// brat documents named after the files. A text without annotations is
+/// read as a document with no annotations.
+pub fn brat_documents(files : &[(String, String)]) -> TeangaResult<Vec<BratDocument>> {
+    let mut txts = HashMap::new();
+    let mut anns = HashMap::new();
+    for (name, content) in files {
+        match name.rsplit_once('.') {
+            Some((stem, "txt")) => { txts.insert(stem, content); },
+            Some((stem, "ann")) => { anns.insert(stem, content); },
+            _ => return Err(TeangaError::BratError(name.clone(),
+                    "brat files must end in .txt or .ann".to_string()))
+        }
+    }
+    if let Some(stem) = anns.keys().find(|stem| !txts.contains_key(*stem)) {
+        return Err(TeangaError::BratError(stem.to_string(),
+            "Annotations have no matching .txt file".to_string()));
+    }
+    let mut docs : Vec<BratDocument> = txts.into_iter().map(|(stem, txt)| BratDocument {
+        id: stem.to_string(),
+        txt: txt.clone(),
+        ann: anns.get(stem).map(|ann| ann.to_string()).unwrap_or_default(),
+    }).collect();
+    docs.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(docs)
+}
+
+/// The annotations of one document, indexed by entity
+#[derive(Debug, Default)]
+struct BratAnnotations {
+    entities: Vec<(usize, usize, Data)>,
+    relations: Vec<(usize, Data)>,
+    attributes: Vec<(usize, Data)>,
+}
+
+/// Read brat documents into a corpus. Text-bound annotations (`T`) become
+/// a span layer of entity types, relations (`R`) an element layer of typed
+/// links between entities and attributes (`A` or `M`) an element layer of
+/// `Name` or `Name=Value` strings on the entities. Other annotations, such
+/// as events and notes, are not read. The documents are named by their
+/// Teanga identifiers and the names of their files are kept in
+/// `Corpus::filenames`.
+pub fn read_corpus_from_brat(docs : &[BratDocument]) -> TeangaResult<Corpus> {
+    let mut documents = Vec::new();
+    let mut entity_types = BTreeSet::new();
+    let mut relation_types = BTreeSet::new();
+    let mut has_attributes = false;
+    for doc in docs {
+        let annos = read_ann(doc).map_err(|msg| TeangaError::BratError(doc.id.clone(), msg))?;
+        let mut content = HashMap::new();
+        content.insert(TEXT_LAYER.to_string(), Layer::Characters(doc.txt.clone()));
+        for (_, _, d) in annos.entities.iter() {
+            entity_types.insert(d.clone().into_str().unwrap_or_default());
+        }
+        for (_, d) in annos.relations.iter() {
+            relation_types.insert(d.clone().into_link().unwrap_or_default().1);
+        }
+        has_attributes |= !annos.attributes.is_empty();
+        if !annos.entities.is_empty() {
+            content.insert(ENTITIES_LAYER.to_string(), Layer::Span(annos.entities));
+        }
+        if !annos.relations.is_empty() {
+            content.insert(RELATIONS_LAYER.to_string(), Layer::Element(annos.relations));
+        }
+        if !annos.attributes.is_empty() {
+            content.insert(ATTRIBUTES_LAYER.to_string(), Layer::Element(annos.attributes));
+        }
+        documents.push(Document { content, defaults: HashSet::new() });
+    }
+    let mut meta = HashMap::new();
+    meta.insert(TEXT_LAYER.to_string(), LayerDesc::new(LayerType::Characters, "", None));
+    meta.insert(ENTITIES_LAYER.to_string(), LayerDesc::new(LayerType::Span, TEXT_LAYER,
+        Some(DataType::Enum(entity_types.into_iter().collect()))));
+    if !relation_types.is_empty() {
+        let mut relations = LayerDesc::new(LayerType::Element, ENTITIES_LAYER,
+            Some(DataType::TypedLink(relation_types.into_iter().collect())));
+        relations.target = Some(ENTITIES_LAYER.to_string());
+        meta.insert(RELATIONS_LAYER.to_string(), relations);
+    }
+    if has_attributes {
+        meta.insert(ATTRIBUTES_LAYER.to_string(), LayerDesc::new(LayerType::Element, ENTITIES_LAYER,
+            Some(DataType::String)));
+    }
+    let mut corpus = Corpus::from_documents(meta, documents);
+    corpus.filenames = corpus.documents.iter().zip(docs)
+        .map(|((id, _), doc)| (id.clone(), doc.id.clone()))
+        .collect();
+    Ok(corpus)
+}
+
+fn read_ann(doc : &BratDocument) -> Result<BratAnnotations, String> {
+    let mut entities = Vec::new();
+    let mut relations = Vec::new();
+    let mut attributes = Vec::new();
+    for (line_no, line) in doc.ann.lines().enumerate().map(|(i, l)| (i + 1, l.trim_end_matches('\r'))) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut fields = line.splitn(3, '\t');
+        let id = fields.next().unwrap_or_default();
+        let body : Vec<&str> = fields.next().unwrap_or_default().split(' ').collect();
+        match id.chars().next() {
+            Some('T') => {
+                if body.len() != 3 {
+                    return Err(format!("Line {}: discontinuous or malformed text-bound annotation {}", line_no, id));
+                }
+                let start = parse_offset(body[1], line_no)?;
+                let end = parse_offset(body[2], line_no)?;
+                let outside = || format!("Line {}: offsets {} {} are outside the text", line_no, start, end);
+                let (start, end) = match fields.next() {
+                    Some(covered) => covered_offsets(&doc.txt, start, end, covered).ok_or_else(|| {
+                        match char_slice(&doc.txt, start, end) {
+                            Some(expected) => format!("Line {}: annotation text {:?} does not match the document text {:?}",
+                                line_no, covered, expected.replace('\n', " ")),
+                            None => outside()
+                        }
+                    })?,
+                    None if char_slice(&doc.txt, start, end).is_none() => return Err(outside()),
+                    None => (start, end)
+                };
+                entities.push((id, start, end, body[0]));
+            },
+            Some('R') => {
+                let arg = |i : usize| body.get(i).and_then(|a| a.split_once(':')).map(|(_, t)| t)
+                    .ok_or_else(|| format!("Line {}: malformed relation {}", line_no, id));
+                relations.push((arg(1)?, arg(2)?, body[0]));
+            },
+            Some('A') | Some('M') => {
+                let entity = body.get(1).ok_or_else(|| format!("Line {}: malformed attribute {}", line_no, id))?;
+                let value = match body.get(2) {
+                    Some(value) => format!("{}={}", body[0], value),
+                    None => body[0].to_string()
+                };
+                attributes.push((*entity, value));
+            },
+            _ => {}
+        }
+    }
+    entities.sort_by(|a, b| a.1.cmp(&b.1).then(a.2.cmp(&b.2)));
+    let index : HashMap<&str, usize> = entities.iter().enumerate().map(|(i, e)| (e.0, i)).collect();
+    let entity = |id : &str| index.get(id).copied()
+        .ok_or_else(|| format!("Annotation refers to unknown entity {}", id));
+    let mut annos = BratAnnotations {
+        entities: entities.iter().map(|(_, start, end, t)| (*start, *end, Data::String(t.to_string()))).collect(),
+        ..Default::default()
+    };
+    for (arg1, arg2, t) in relations {
+        annos.relations.push((entity(arg1)?, Data::TypedLink(entity(arg2)?, t.to_string())));
+    }
+    for (e, value) in attributes {
+        annos.attributes.push((entity(e)?, Data::String(value)));
+    }
+    annos.relations.sort_by_key(|(i, _)| *i);
+    annos.attributes.sort_by_key(|(i, _)| *i);
+    Ok(annos)
+}
+
+/// The code point offsets of a text-bound annotation that covers a text. The
+/// offsets are read as code points, or else as UTF-16 code units, as counted
+/// by JavaScript tools, or as UTF-8 bytes, whichever first gives the text.
+fn covered_offsets(txt : &str, start : usize, end : usize, covered : &str) -> Option<(usize, usize)> {
+    let counts : [fn(&str, usize) -> Option<usize>; 3] = [|_, i| Some(i), utf16_to_char, byte_to_char];
+    counts.iter()
+        .filter_map(|to_char| Some((to_char(txt, start)?, to_char(txt, end)?)))
+        .find(|(start, end)| char_slice(txt, *start, *end).is_some_and(|s| s.replace('\n', " ") == covered))
+}
+
+fn parse_offset(s : &str, line_no : usize) -> Result<usize, String> {
+    s.parse::<usize>().map_err(|_| format!("Line {}: invalid offset {}", line_no, s))
+}
+
+/// The layers written to brat files
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BratLayers {
+    pub entities: String,
+    pub relations: Option<String>,
+    pub attributes: Option<String>,
+}
+
+impl BratLayers {
+    /// Choose the layers for a corpus, preferring the layer names used when
+    /// importing brat files
+    pub fn guess(meta : &HashMap<String, LayerDesc>) -> BratLayers {
+        let mut spans : Vec<&String> = meta.iter().filter(|(_, desc)| desc.layer_type == LayerType::Span &&
+                meta.get(&desc.on).is_some_and(|on| on.layer_type == LayerType::Characters))
+            .map(|(name, _)| name).collect();
+        spans.sort();
+        let entities = if meta.contains_key(ENTITIES_LAYER) {
+            ENTITIES_LAYER.to_string()
+        } else {
+            spans.first().map(|s| s.to_string()).unwrap_or_default()
+        };
+        let on_entities = |name : &str| meta.get(name)
+            .is_some_and(|desc| desc.on == entities).then(|| name.to_string());
+        BratLayers {
+            relations: on_entities(RELATIONS_LAYER),
+            attributes: on_entities(ATTRIBUTES_LAYER),
+            entities,
+        }
+    }
+}
+
+/// Write a corpus as brat documents in document order. The entities must be
+/// a span layer on a characters layer, and relations and attributes are
+/// layers on the entities. Span layers without data use the layer name as
+/// entity type and untyped links use the relation layer name as relation
+/// type. Documents are named by the file name they were read from, if it is
+/// known and not shared with another document, or else by their identifier.
+pub fn write_corpus_to_brat(corpus : &Corpus, layers : &BratLayers) -> TeangaResult<Vec<BratDocument>> {
+    let entities_meta = corpus.meta.get(&layers.entities).ok_or_else(||
+        TeangaError::MissingMeta(layers.entities.clone()))?;
+    if entities_meta.layer_type != LayerType::Span ||
+        !corpus.meta.get(&entities_meta.on).is_some_and(|on| on.layer_type == LayerType::Characters) {
+        return Err(TeangaError::ModelError(format!(
+            "Entity layer {} must be a span layer on a characters layer", layers.entities)));
+    }
+    for layer in layers.relations.iter().chain(layers.attributes.iter()) {
+        if corpus.meta.get(layer).is_none_or(|desc| desc.on != layers.entities) {
+            return Err(TeangaError::ModelError(format!(
+                "Layer {} must be a layer on {}", layer, layers.entities)));
+        }
+    }
+    let filename = |id : &str| corpus.filenames.get(id).filter(|name| !name.is_empty());
+    let mut counts : HashMap<&String, usize> = HashMap::new();
+    for name in corpus.documents.iter().filter_map(|(id, _)| filename(id)) {
+        *counts.entry(name).or_default() += 1;
+    }
+    corpus.doc_order().into_iter().map(|i| {
+        let (id, doc) = &corpus.documents[i];
+        let name = filename(id).filter(|name| counts[name] == 1).unwrap_or(id).clone();
+        write_document(&name, doc, &entities_meta.on, layers)
+            .map_err(|msg| TeangaError::BratError(id.clone(), msg))
+    }).collect()
+}
+
+/// Pack brat documents into a zip archive of their `.txt` and `.ann` files,
+/// so that they can be saved as one file
+pub fn write_brat_zip(docs : &[BratDocument]) -> TeangaResult<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for doc in docs {
+        for (ext, content) in [("txt", &doc.txt), ("ann", &doc.ann)] {
+            zip.start_file(format!("{}.{}", doc.id, ext), SimpleFileOptions::default())?;
+            zip.write_all(content.as_bytes()).map_err(zip::result::ZipError::from)?;
+        }
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+fn write_document(id : &str, doc : &Document, text_layer : &str, layers : &BratLayers) -> Result<BratDocument, String> {
+    let txt = match doc.content.get(text_layer) {
+        Some(Layer::Characters(s)) => s.clone(),
+        _ => return Err(format!("No text in layer {}", text_layer))
+    };
+    let entities : Vec<(usize, usize, String)> = match doc.content.get(&layers.entities) {
+        None => Vec::new(),
+        Some(Layer::SpanNoData(spans)) => spans.iter().map(|(i, j)| (*i, *j, layers.entities.clone())).collect(),
+        Some(Layer::Span(spans)) => spans.iter().map(|(i, j, d)| match d {
+            Data::String(s) => Ok((*i, *j, s.clone())),
+            _ => Err(format!("Entity layer {} contains non-string data", layers.entities))
+        }).collect::<Result<_, _>>()?,
+        Some(_) => return Err(format!("Entity layer {} is not a span layer", layers.entities))
+    };
+    let mut ann = String::new();
+    for (k, (start, end, t)) in entities.iter().enumerate() {
+        let Some(covered) = char_slice(&txt, *start, *end) else {
+            return Err(format!("Entity {} has an invalid span {}..{}", k, start, end));
+        };
+        if t.is_empty() || t.contains(char::is_whitespace) {
+            return Err(format!("Entity type {:?} is not a valid brat type", t));
+        }
+        let covered = covered.replace('\n', " ");
+        ann.push_str(&format!("T{}\t{} {} {}\t{}\n", k + 1, t, start, end, covered));
+    }
+    let entity = |i : usize| if i < entities.len() {
+        Ok(format!("T{}", i + 1))
+    } else {
+        Err(format!("Annotation refers to missing entity {}", i))
+    };
+    if let Some(relations) = &layers.relations {
+        let items : Vec<(usize, &Data)> = match doc.content.get(relations) {
+            None => Vec::new(),
+            Some(Layer::Seq(data)) => data.iter().enumerate().collect(),
+            Some(Layer::Element(data)) => data.iter().map(|(i, d)| (*i, d)).collect(),
+            Some(_) => return Err(format!("Relation layer {} must be a seq or element layer", relations))
+        };
+        for (k, (i, d)) in items.into_iter().enumerate() {
+            let (j, t) = match d {
+                Data::TypedLink(j, t) => (*j, t.as_str()),
+                Data::Link(j) => (*j, relations.as_str()),
+                Data::String(_) => return Err(format!("Relation layer {} contains non-link data", relations))
+            };
+            ann.push_str(&format!("R{}\t{} Arg1:{} Arg2:{}\t\n", k + 1, t, entity(i)?, entity(j)?));
+        }
+    }
+    if let Some(attributes) = &layers.attributes {
+        let items : Vec<(usize, &Data)> = match doc.content.get(attributes) {
+            None => Vec::new(),
+            Some(Layer::Seq(data)) => data.iter().enumerate().collect(),
+            Some(Layer::Element(data)) => data.iter().map(|(i, d)| (*i, d)).collect(),
+            Some(_) => return Err(format!("Attribute layer {} must be a seq or element layer", attributes))
+        };
+        for (k, (i, d)) in items.into_iter().enumerate() {
+            let attribute = match d {
+                Data::String(s) => match s.split_once('=') {
+                    Some((name, value)) => format!("{} {} {}", name, entity(i)?, value),
+                    None => format!("{} {}", s, entity(i)?)
+                },
+                _ => return Err(format!("Attribute layer {} contains non-string data", attributes))
+            };
+            ann.push_str(&format!("A{}\t{}\n", k + 1, attribute));
+        }
+    }
+    Ok(BratDocument { id: id.to_string(), txt, ann })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brat_docs() -> Vec<BratDocument> {
+        vec![BratDocument {
+            id: "doc1".to_string(),
+            txt: "Tá Seán ag obair i mBaile Átha Cliath.\n".to_string(),
+            ann: "T2\tLOC 19 37\tmBaile Átha Cliath\nT1\tPER 3 7\tSeán\nR1\tLives_in Arg1:T1 Arg2:T2\t\nA1\tNegated T2\nA2\tConfidence T1 High\n".to_string(),
+        }, BratDocument {
+            id: "doc2".to_string(),
+            txt: "No entities here".to_string(),
+            ann: String::new(),
+        }]
+    }
+
+    #[test]
+    fn test_read_brat() {
+        let corpus = read_corpus_from_brat(&brat_docs()).unwrap();
+        assert_eq!(corpus.order.len(), 2);
+        let doc = &corpus.documents[0].1;
+        assert_eq!(doc.content[ENTITIES_LAYER], Layer::Span(vec![
+            (3, 7, Data::String("PER".to_string())),
+            (19, 37, Data::String("LOC".to_string()))]));
+        assert_eq!(doc.content[RELATIONS_LAYER], Layer::Element(vec![
+            (0, Data::TypedLink(1, "Lives_in".to_string()))]));
+        assert_eq!(doc.content[ATTRIBUTES_LAYER], Layer::Element(vec![
+            (0, Data::String("Confidence=High".to_string())),
+            (1, Data::String("Negated".to_string()))]));
+        assert!(!corpus.documents[1].1.content.contains_key(ENTITIES_LAYER));
+        assert_eq!(corpus.filenames[&corpus.documents[1].0], "doc2");
+        assert!(!corpus.meta.contains_key("filename"));
+        assert_eq!(corpus.meta[ENTITIES_LAYER].data, Some(DataType::Enum(
+            vec!["LOC".to_string(), "PER".to_string()])));
+    }
+
+    #[test]
+    fn test_brat_round_trip() {
+        let corpus = read_corpus_from_brat(&brat_docs()).unwrap();
+        let docs = write_corpus_to_brat(&corpus, &BratLayers::guess(&corpus.meta)).unwrap();
+        assert_eq!(docs.iter().map(|doc| doc.id.as_str()).collect::<Vec<_>>(), vec!["doc1", "doc2"]);
+        assert_eq!(docs[0].ann, "T1\tPER 3 7\tSeán\nT2\tLOC 19 37\tmBaile Átha Cliath\nR1\tLives_in Arg1:T1 Arg2:T2\t\nA1\tConfidence T1 High\nA2\tNegated T2\n");
+        assert_eq!(docs[1].ann, "");
+        let corpus2 = read_corpus_from_brat(&docs).unwrap();
+        assert_eq!(corpus.documents, corpus2.documents);
+    }
+
+    #[test]
+    fn test_write_brat_order() {
+        let mut corpus = read_corpus_from_brat(&brat_docs()).unwrap();
+        corpus.order.reverse();
+        // A file name shared by two documents is replaced by their identifiers
+        corpus.filenames.insert(corpus.documents[0].0.clone(), "doc2".to_string());
+        let docs = write_corpus_to_brat(&corpus, &BratLayers::guess(&corpus.meta)).unwrap();
+        assert_eq!(docs[0].id, corpus.order[0]);
+        assert_eq!(docs[1].id, corpus.order[1]);
+        assert_eq!(docs[0].txt, "No entities here");
+    }
+
+    #[test]
+    fn test_write_brat_zip() {
+        let zip = write_brat_zip(&brat_docs()).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(zip)).unwrap();
+        assert_eq!(archive.file_names().count(), 4);
+        let mut txt = String::new();
+        std::io::Read::read_to_string(&mut archive.by_name("doc1.txt").unwrap(), &mut txt).unwrap();
+        assert_eq!(txt, brat_docs()[0].txt);
+    }
+
+    #[test]
+    fn test_brat_documents() {
+        let files = vec![("b.ann".to_string(), "T1\tX 0 1\ta\n".to_string()),
+            ("b.txt".to_string(), "a".to_string()), ("a.txt".to_string(), "b".to_string())];
+        let docs = brat_documents(&files).unwrap();
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs[0].id, "a");
+        assert_eq!(docs[0].ann, "");
+        assert_eq!(docs[1].ann, "T1\tX 0 1\ta\n");
+        assert!(brat_documents(&files[..1]).is_err());
+    }
+
+    #[test]
+    fn test_read_brat_offset_mismatch() {
+        let docs = vec![BratDocument {
+            id: "doc".to_string(),
+            txt: "Tá Seán anseo".to_string(),
+            ann: "T1\tPER 4 8\tSeán\n".to_string(),
+        }];
+        assert!(matches!(read_corpus_from_brat(&docs), Err(TeangaError::BratError(_, _))));
+    }
+
+    #[test]
+    fn test_read_brat_other_offsets() {
+        // "Seán" is at 3-7 in code points, 5-9 in UTF-16 code units and 9-14
+        // in UTF-8 bytes
+        let docs = vec![BratDocument {
+            id: "doc".to_string(),
+            txt: "👋🏽 Seán".to_string(),
+            ann: "T1\tPER 3 7\tSeán\nT2\tPER 5 9\tSeán\nT3\tPER 9 14\tSeán\n".to_string(),
+        }];
+        let corpus = read_corpus_from_brat(&docs).unwrap();
+        assert_eq!(corpus.documents[0].1.content[ENTITIES_LAYER], Layer::Span(
+            vec![(3, 7, Data::String("PER".to_string())); 3]));
+    }
+}
@@ -0,0 +1,557 @@
+//! Conversion between CoNLL-U files and Teanga corpora
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+use crate::teanga::{Corpus, Document, Layer, LayerDesc, LayerType, DataType, Data, TEXT_LAYER};
+use crate::serialization::{TeangaError, TeangaResult};
+
+/// The names of the layers created when importing a CoNLL-U file, besides
+/// its text
+pub const TOKENS_LAYER : &str = "tokens";
+pub const UPOS_LAYER : &str = "upos";
+pub const XPOS_LAYER : &str = "xpos";
+pub const LEMMA_LAYER : &str = "lemma";
+pub const FEATS_LAYER : &str = "feats";
+pub const DEP_LAYER : &str = "dep";
+pub const FORM_LAYER : &str = "form";
+
+/// A sentence as it is being read from the CoNLL-U file
+#[derive(Debug, Default)]
+struct Sentence {
+    text: String,
+    text_len: usize,
+    tokens: Vec<(usize, usize)>,
+    upos: Vec<Data>,
+    xpos: Vec<Data>,
+    lemma: Vec<Data>,
+    feats: Vec<Data>,
+    /// The HEAD, DEPREL and line of each word, without a HEAD if it is `_`
+    heads: Vec<(Option<usize>, String, usize)>,
+    /// The FORM of each word, which differs from the text for the words of a
+    /// multi-word token
+    forms: Vec<Data>,
+    /// The last word id and the span of the current multi-word token
+    mwt: Option<(usize, (usize, usize))>,
+}
+
+impl Sentence {
+    fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Append a surface form to the text and return its character span
+    fn push_form(&mut self, form : &str, misc : &str) -> (usize, usize) {
+        let start = self.text_len;
+        self.text.push_str(form);
+        self.text_len += form.chars().count();
+        let end = self.text_len;
+        if !misc.split('|').any(|m| m == "SpaceAfter=No") {
+            self.text.push(' ');
+            self.text_len += 1;
+        }
+        (start, end)
+    }
+
+    /// Convert the sentence into a document
+    fn into_document(mut self) -> TeangaResult<Document> {
+        if self.text.ends_with(' ') {
+            self.text.pop();
+        }
+        let n = self.tokens.len();
+        let parsed = self.heads.iter().any(|(head, _, _)| head.is_some());
+        let mut links = Vec::new();
+        for (i, (head, deprel, line_no)) in self.heads.into_iter().enumerate() {
+            let Some(head) = head else {
+                if parsed {
+                    return Err(TeangaError::ConlluError(line_no,
+                        "HEAD is `_` but other words of the sentence have a HEAD".to_string()));
+                }
+                continue;
+            };
+            if head > n {
+                return Err(TeangaError::ConlluError(line_no,
+                    format!("HEAD {} is not a word of the sentence", head)));
+            }
+            // The root is stored as a link of the word to itself, as a
+            // word can never be its own head in CoNLL-U
+            links.push(Data::TypedLink(if head == 0 { i } else { head - 1 }, deprel));
+        }
+        let mut content = HashMap::new();
+        content.insert(TEXT_LAYER.to_string(), Layer::Characters(self.text));
+        content.insert(TOKENS_LAYER.to_string(), Layer::SpanNoData(self.tokens));
+        content.insert(UPOS_LAYER.to_string(), Layer::Seq(self.upos));
+        content.insert(XPOS_LAYER.to_string(), Layer::Seq(self.xpos));
+        content.insert(LEMMA_LAYER.to_string(), Layer::Seq(self.lemma));
+        content.insert(FEATS_LAYER.to_string(), Layer::Seq(self.feats));
+        if parsed {
+            content.insert(DEP_LAYER.to_string(), Layer::Seq(links));
+        }
+        content.insert(FORM_LAYER.to_string(), Layer::Seq(self.forms));
+        Ok(Document { content, defaults: HashSet::new() })
+    }
+}
+
+/// Read a CoNLL-U file into a corpus. Each sentence becomes a document,
+/// named by its Teanga identifier, with the text rebuilt from the FORM and
+/// SpaceAfter columns, a span layer of words and sequence layers for UPOS,
+/// XPOS, LEMMA, FEATS and HEAD+DEPREL. Columns that are empty (`_`)
+/// throughout the file are left out, and a sentence whose HEAD column is
+/// empty has no HEAD+DEPREL layer. Empty nodes are skipped and the words
+/// of a multi-word token share its span, with their own FORM kept in a
+/// sequence layer that is only included if the file has multi-word tokens.
+pub fn read_corpus_from_conllu_string(s : &str) -> TeangaResult<Corpus> {
+    let mut docs = Vec::new();
+    let mut sentence = Sentence::default();
+    // Whether the UPOS, XPOS, LEMMA and FEATS columns are used, whether
+    // there are multi-word tokens and whether the HEAD column is used
+    let mut used = [false; 6];
+    let mut deprels = BTreeSet::new();
+    for (line_no, line) in s.lines().enumerate().map(|(i, l)| (i + 1, l.trim_end_matches('\r'))) {
+        if line.trim().is_empty() {
+            if !sentence.is_empty() {
+                docs.push(std::mem::take(&mut sentence).into_document()?);
+            }
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        let cols : Vec<&str> = line.split('\t').collect();
+        if cols.len() != 10 {
+            return Err(TeangaError::ConlluError(line_no,
+                format!("Expected 10 columns but found {}", cols.len())));
+        }
+        let id = cols[0];
+        if id.contains('.') {
+            // Empty nodes of the enhanced graph have no place in the text
+            continue;
+        }
+        if let Some((first, last)) = id.split_once('-') {
+            let first = parse_id(first, line_no)?;
+            let last = parse_id(last, line_no)?;
+            if first != sentence.tokens.len() + 1 || last < first {
+                return Err(TeangaError::ConlluError(line_no,
+                    format!("Unexpected multi-word token {}", id)));
+            }
+            let span = sentence.push_form(cols[1], cols[9]);
+            sentence.mwt = Some((last, span));
+            used[4] = true;
+            continue;
+        }
+        let id = parse_id(id, line_no)?;
+        if id != sentence.tokens.len() + 1 {
+            return Err(TeangaError::ConlluError(line_no,
+                format!("Expected word {} but found {}", sentence.tokens.len() + 1, id)));
+        }
+        let span = match sentence.mwt {
+            Some((last, span)) if id <= last => span,
+            _ => {
+                sentence.mwt = None;
+                sentence.push_form(cols[1], cols[9])
+            }
+        };
+        sentence.tokens.push(span);
+        sentence.forms.push(Data::String(cols[1].to_string()));
+        for (i, (col, layer)) in [(3, &mut sentence.upos), (4, &mut sentence.xpos),
+            (2, &mut sentence.lemma), (5, &mut sentence.feats)].into_iter().enumerate() {
+            used[i] |= cols[col] != "_";
+            layer.push(Data::String(cols[col].to_string()));
+        }
+        let head = match cols[6] {
+            "_" => None,
+            head => Some(head.parse::<usize>().map_err(|_| TeangaError::ConlluError(line_no,
+                format!("HEAD must be a number or _ but was {}", head)))?)
+        };
+        used[5] |= head.is_some();
+        if head.is_some() {
+            deprels.insert(cols[7].to_string());
+        }
+        sentence.heads.push((head, cols[7].to_string(), line_no));
+    }
+    if !sentence.is_empty() {
+        docs.push(sentence.into_document()?);
+    }
+    let mut meta = conllu_meta(deprels.into_iter().collect());
+    for (i, layer) in [UPOS_LAYER, XPOS_LAYER, LEMMA_LAYER, FEATS_LAYER, FORM_LAYER, DEP_LAYER].iter().enumerate() {
+        if !used[i] {
+            meta.remove(*layer);
+            for doc in docs.iter_mut() {
+                doc.content.remove(*layer);
+            }
+        }
+    }
+    Ok(Corpus::from_documents(meta, docs))
+}
+
+/// The layers used to fill the columns of a CoNLL-U file. The tokens must
+/// be a span layer on a characters layer, the other columns are sequence
+/// layers on the tokens.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ConlluColumns {
+    pub tokens: String,
+    /// The FORM of the words of multi-word tokens, which is otherwise `_`
+    pub form: Option<String>,
+    pub lemma: Option<String>,
+    pub upos: Option<String>,
+    pub xpos: Option<String>,
+    pub feats: Option<String>,
+    pub head: Option<String>,
+}
+
+impl ConlluColumns {
+    /// The names of the columns that can be chosen
+    pub const COLUMNS : [&'static str; 7] = ["tokens", "form", "lemma", "upos", "xpos", "feats", "head"];
+
+    /// Choose the columns for a corpus, preferring the layer names used
+    /// when importing CoNLL-U
+    pub fn guess(meta : &HashMap<String, LayerDesc>) -> ConlluColumns {
+        let mut columns = ConlluColumns::default();
+        let tokens = Self::candidates(meta, "tokens", "");
+        columns.tokens = if tokens.iter().any(|t| t == TOKENS_LAYER) {
+            TOKENS_LAYER.to_string()
+        } else {
+            tokens.into_iter().next().unwrap_or_default()
+        };
+        for (column, layer) in [("form", FORM_LAYER), ("lemma", LEMMA_LAYER), ("upos", UPOS_LAYER),
+            ("xpos", XPOS_LAYER), ("feats", FEATS_LAYER), ("head", DEP_LAYER)] {
+            if Self::candidates(meta, column, &columns.tokens).iter().any(|c| c == layer) {
+                columns.set(column, Some(layer.to_string()));
+            }
+        }
+        columns
+    }
+
+    /// The layers that can be used for a column, given the tokens layer
+    pub fn candidates(meta : &HashMap<String, LayerDesc>, column : &str, tokens : &str) -> Vec<String> {
+        let mut candidates : Vec<String> = meta.iter().filter(|(_, desc)| {
+            match column {
+                "tokens" => desc.layer_type == LayerType::Span &&
+                    meta.get(&desc.on).is_some_and(|on| on.layer_type == LayerType::Characters),
+                "head" => desc.layer_type == LayerType::Seq && desc.on == tokens &&
+                    matches!(desc.data, Some(DataType::Link) | Some(DataType::TypedLink(_))),
+                _ => desc.layer_type == LayerType::Seq && desc.on == tokens &&
+                    matches!(desc.data, Some(DataType::String) | Some(DataType::Enum(_)))
+            }
+        }).map(|(name, _)| name.clone()).collect();
+        candidates.sort();
+        candidates
+    }
+
+    /// The layer chosen for a column
+    pub fn get(&self, column : &str) -> Option<&String> {
+        match column {
+            "tokens" => Some(&self.tokens).filter(|t| !t.is_empty()),
+            "form" => self.form.as_ref(),
+            "lemma" => self.lemma.as_ref(),
+            "upos" => self.upos.as_ref(),
+            "xpos" => self.xpos.as_ref(),
+            "feats" => self.feats.as_ref(),
+            "head" => self.head.as_ref(),
+            _ => None
+        }
+    }
+
+    /// Choose the layer for a column
+    pub fn set(&mut self, column : &str, layer : Option<String>) {
+        match column {
+            "tokens" => self.tokens = layer.unwrap_or_default(),
+            "form" => self.form = layer,
+            "lemma" => self.lemma = layer,
+            "upos" => self.upos = layer,
+            "xpos" => self.xpos = layer,
+            "feats" => self.feats = layer,
+            "head" => self.head = layer,
+            _ => {}
+        }
+    }
+}
+
+/// Write a corpus as CoNLL-U with one sentence per document. Identical
+/// consecutive token spans are written as a multi-word token and a link
+/// of a word to itself is written as the root. Anything that CoNLL-U cannot
+/// represent, such as overlapping tokens or text outside of the tokens,
+/// is reported as an error rather than dropped.
+pub fn write_corpus_to_conllu_string(corpus : &Corpus, columns : &ConlluColumns) -> TeangaResult<String> {
+    let tokens_meta = corpus.meta.get(&columns.tokens).ok_or_else(||
+        TeangaError::MissingMeta(columns.tokens.clone()))?;
+    if tokens_meta.layer_type != LayerType::Span ||
+        !corpus.meta.get(&tokens_meta.on).is_some_and(|on| on.layer_type == LayerType::Characters) {
+        return Err(TeangaError::ModelError(format!(
+            "Tokens layer {} must be a span layer on a characters layer", columns.tokens)));
+    }
+    for column in ConlluColumns::COLUMNS.iter().skip(1) {
+        if let Some(layer) = columns.get(column) {
+            if !ConlluColumns::candidates(&corpus.meta, column, &columns.tokens).contains(layer) {
+                return Err(TeangaError::ModelError(format!(
+                    "Layer {} cannot be used for the {} column", layer, column)));
+            }
+        }
+    }
+    let mut out = String::new();
+    for (id, doc) in corpus.doc_order().into_iter().map(|i| &corpus.documents[i]) {
+        write_document(&mut out, id, doc, &tokens_meta.on, columns)
+            .map_err(|msg| TeangaError::ConlluExportError(id.clone(), msg))?;
+    }
+    Ok(out)
+}
+
+fn write_document(out : &mut String, id : &str, doc : &Document, text_layer : &str,
+    columns : &ConlluColumns) -> Result<(), String> {
+    let text : Vec<char> = match doc.content.get(text_layer) {
+        Some(Layer::Characters(s)) => s.chars().collect(),
+        _ => return Err(format!("No text in layer {}", text_layer))
+    };
+    let tokens : Vec<(usize, usize)> = match doc.content.get(&columns.tokens) {
+        Some(Layer::SpanNoData(spans)) => spans.clone(),
+        Some(Layer::Span(spans)) => spans.iter().map(|(i, j, _)| (*i, *j)).collect(),
+        _ => return Err(format!("No tokens in layer {}", columns.tokens))
+    };
+    let mut last_end = 0;
+    for (i, (start, end)) in tokens.iter().enumerate() {
+        if start >= end || *end > text.len() {
+            return Err(format!("Token {} has an invalid span {}..{}", i, start, end));
+        }
+        if text[*start..*end].iter().any(|c| matches!(c, '\t' | '\n')) {
+            return Err(format!("Token {} contains a tab or newline", i));
+        }
+        if i > 0 && tokens[i - 1] == (*start, *end) {
+            continue;
+        }
+        if *start < last_end {
+            return Err(format!("Token {} overlaps the previous token", i));
+        }
+        if text[last_end..*start].iter().any(|c| !c.is_whitespace()) {
+            return Err(format!("Text before token {} is not covered by any token", i));
+        }
+        last_end = *end;
+    }
+    if text[last_end..].iter().any(|c| !c.is_whitespace()) {
+        return Err("Text after the last token is not covered by any token".to_string());
+    }
+    let column_data = |column : &str| -> Result<Option<&Vec<Data>>, String> {
+        match columns.get(column) {
+            None => Ok(None),
+            Some(layer) => match doc.content.get(layer) {
+                Some(Layer::Seq(data)) if data.len() == tokens.len() => Ok(Some(data)),
+                Some(Layer::Seq(data)) => Err(format!("Layer {} has {} values for {} tokens",
+                        layer, data.len(), tokens.len())),
+                _ => Err(format!("Missing layer {}", layer))
+            }
+        }
+    };
+    let string_column = |column : &str| -> Result<Vec<String>, String> {
+        match column_data(column)? {
+            None => Ok(vec!["_".to_string(); tokens.len()]),
+            Some(data) => data.iter().map(|d| match d {
+                Data::String(s) if s.contains(['\t', '\n']) => Err(format!(
+                        "Value {:?} of the {} column contains a tab or newline", s, column)),
+                Data::String(s) => Ok(s.clone()),
+                _ => Err(format!("The {} column contains non-string data", column))
+            }).collect()
+        }
+    };
+    let forms = column_data("form")?.map(|_| string_column("form")).transpose()?;
+    let lemma = string_column("lemma")?;
+    let upos = string_column("upos")?;
+    let xpos = string_column("xpos")?;
+    let feats = string_column("feats")?;
+    let heads = match column_data("head")? {
+        None => vec![("_".to_string(), "_".to_string()); tokens.len()],
+        Some(data) => data.iter().enumerate().map(|(i, d)| {
+            let (head, deprel) = match d {
+                Data::Link(j) => (*j, "_".to_string()),
+                Data::TypedLink(j, deprel) => (*j, deprel.clone()),
+                _ => return Err("The head column contains non-link data".to_string())
+            };
+            if head >= tokens.len() {
+                Err(format!("Token {} links to missing token {}", i, head))
+            } else if head == i {
+                Ok(("0".to_string(), deprel))
+            } else {
+                Ok(((head + 1).to_string(), deprel))
+            }
+        }).collect::<Result<Vec<_>, String>>()?
+    };
+    let form = |(start, end) : (usize, usize)| -> String { text[start..end].iter().collect() };
+    let space_after = |end : usize| end >= text.len() || text[end].is_whitespace();
+    // Writing to a String cannot fail
+    writeln!(out, "# sent_id = {}", id).unwrap();
+    writeln!(out, "# text = {}",
+        text.iter().map(|c| if c.is_whitespace() { ' ' } else { *c }).collect::<String>().trim()).unwrap();
+    let mut i = 0;
+    while i < tokens.len() {
+        let span = tokens[i];
+        let n = tokens[i..].iter().take_while(|t| **t == span).count();
+        let is_last = i + n == tokens.len();
+        let misc = if space_after(span.1) || is_last { "_" } else { "SpaceAfter=No" };
+        if n > 1 {
+            writeln!(out, "{}-{}\t{}\t_\t_\t_\t_\t_\t_\t_\t{}", i + 1, i + n, form(span), misc).unwrap();
+        }
+        for k in i..i + n {
+            let (word, misc) = match &forms {
+                _ if n == 1 => (form(span), misc),
+                Some(forms) => (forms[k].clone(), "_"),
+                None => ("_".to_string(), "_")
+            };
+            writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t_\t{}",
+                k + 1, word, lemma[k], upos[k], xpos[k], feats[k], heads[k].0, heads[k].1, misc).unwrap();
+        }
+        i += n;
+    }
+    out.push('\n');
+    Ok(())
+}
+
+fn parse_id(id : &str, line_no : usize) -> TeangaResult<usize> {
+    id.parse::<usize>().map_err(|_| TeangaError::ConlluError(line_no,
+        format!("Invalid word id {}", id)))
+}
+
+/// The layer descriptions of a corpus read from CoNLL-U
+fn conllu_meta(deprels : Vec<String>) -> HashMap<String, LayerDesc> {
+    let mut meta = HashMap::new();
+    meta.insert(TEXT_LAYER.to_string(), LayerDesc::new(LayerType::Characters, "", None));
+    meta.insert(TOKENS_LAYER.to_string(), LayerDesc::new(LayerType::Span, TEXT_LAYER, None));
+    for layer in [UPOS_LAYER, XPOS_LAYER, LEMMA_LAYER, FEATS_LAYER] {
+        meta.insert(layer.to_string(), LayerDesc::new(LayerType::Seq, TOKENS_LAYER, Some(DataType::String)));
+    }
+    let mut dep = LayerDesc::new(LayerType::Seq, TOKENS_LAYER, Some(DataType::TypedLink(deprels)));
+    dep.target = Some(TOKENS_LAYER.to_string());
+    meta.insert(DEP_LAYER.to_string(), dep);
+    meta.insert(FORM_LAYER.to_string(), LayerDesc::new(LayerType::Seq, TOKENS_LAYER, Some(DataType::String)));
+    meta
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONLLU : &str = "# sent_id = test-1
+# text = Vámonos al mar.
+1-2\tVámonos\t_\t_\t_\t_\t_\t_\t_\t_
+1\tVamos\tir\tVERB\t_\tMood=Imp\t0\troot\t_\t_
+2\tnos\tnosotros\tPRON\t_\tCase=Acc\t1\tobj\t_\t_
+3-4\tal\t_\t_\t_\t_\t_\t_\t_\t_
+3\ta\ta\tADP\t_\t_\t5\tcase\t_\t_
+4\tel\tel\tDET\t_\t_\t5\tdet\t_\t_
+5\tmar\tmar\tNOUN\t_\t_\t1\tobl\t_\tSpaceAfter=No
+6\t.\t.\tPUNCT\t_\t_\t1\tpunct\t_\t_
+
+1\tHello\thello\tINTJ\t_\t_\t0\troot\t_\t_
+1.1\tthere\tthere\tADV\t_\t_\t_\t_\t1:advmod\t_
+";
+
+    #[test]
+    fn test_read_conllu() {
+        let corpus = read_corpus_from_conllu_string(CONLLU).unwrap();
+        assert_eq!(corpus.order.len(), 2);
+        assert!(!corpus.meta.contains_key(XPOS_LAYER));
+        let doc = &corpus.documents[0].1;
+        assert_eq!(doc.content[TEXT_LAYER], Layer::Characters("Vámonos al mar.".to_string()));
+        assert_eq!(doc.content[TOKENS_LAYER], Layer::SpanNoData(
+                vec![(0, 7), (0, 7), (8, 10), (8, 10), (11, 14), (14, 15)]));
+        match &doc.content[DEP_LAYER] {
+            Layer::Seq(links) => {
+                assert_eq!(links[0], Data::TypedLink(0, "root".to_string()));
+                assert_eq!(links[2], Data::TypedLink(4, "case".to_string()));
+            },
+            l => panic!("Unexpected layer {:?}", l)
+        }
+        assert_eq!(doc.content[FORM_LAYER], Layer::Seq(["Vamos", "nos", "a", "el", "mar", "."].into_iter()
+            .map(|f| Data::String(f.to_string())).collect()));
+        let doc = &corpus.documents[1].1;
+        assert_eq!(doc.content[TOKENS_LAYER], Layer::SpanNoData(vec![(0, 5)]));
+        assert_eq!(corpus.meta[DEP_LAYER].data, Some(DataType::TypedLink(
+            vec!["case", "det", "obj", "obl", "punct", "root"].into_iter().map(String::from).collect())));
+    }
+
+    #[test]
+    fn test_conllu_arcs() {
+        // The words of "Vámonos" and "al" share their offsets but are still
+        // separate words, so "nos" is not drawn as a second root
+        let corpus = read_corpus_from_conllu_string(CONLLU).unwrap();
+        let diagram = crate::arcs::link_arcs(&corpus.documents[0].1, DEP_LAYER, &corpus.meta).unwrap();
+        assert_eq!(diagram.words.iter().map(|w| (w.start, w.end, w.item.1)).collect::<Vec<_>>(),
+            vec![(0, 7, 0), (0, 7, 1), (8, 10, 2), (8, 10, 3), (11, 14, 4), (14, 15, 5)]);
+        let arcs : Vec<(usize, usize, &str)> = diagram.arcs.iter()
+            .map(|a| (a.head, a.dependent, a.label.as_deref().unwrap())).collect();
+        assert_eq!(arcs, vec![(0, 0, "root"), (0, 1, "obj"), (4, 2, "case"), (4, 3, "det"), (0, 4, "obl"), (0, 5, "punct")]);
+        assert_eq!(diagram.chunks().len(), 1);
+    }
+
+    #[test]
+    fn test_conllu_round_trip() {
+        let corpus = read_corpus_from_conllu_string(CONLLU).unwrap();
+        let columns = ConlluColumns::guess(&corpus.meta);
+        assert_eq!(columns.head, Some(DEP_LAYER.to_string()));
+        assert_eq!(columns.xpos, None);
+        assert_eq!(columns.form, Some(FORM_LAYER.to_string()));
+        let conllu = write_corpus_to_conllu_string(&corpus, &columns).unwrap();
+        assert!(conllu.contains("# text = Vámonos al mar.\n"));
+        assert!(conllu.contains("1-2\tVámonos\t_\t_\t_\t_\t_\t_\t_\t_\n"));
+        assert!(conllu.contains("1\tVamos\tir\tVERB\t_\tMood=Imp\t0\troot\t_\t_\n"));
+        assert!(conllu.contains("5\tmar\tmar\tNOUN\t_\t_\t1\tobl\t_\tSpaceAfter=No\n"));
+        let corpus2 = read_corpus_from_conllu_string(&conllu).unwrap();
+        assert_eq!(corpus2.documents[0].1.content[TEXT_LAYER], corpus.documents[0].1.content[TEXT_LAYER]);
+        assert_eq!(corpus2.documents[0].1.content[TOKENS_LAYER], corpus.documents[0].1.content[TOKENS_LAYER]);
+        assert_eq!(corpus2.documents[0].1.content[DEP_LAYER], corpus.documents[0].1.content[DEP_LAYER]);
+        assert_eq!(corpus2.documents[0].1.content[FORM_LAYER], corpus.documents[0].1.content[FORM_LAYER]);
+        assert_eq!(corpus2.documents[1].1, corpus.documents[1].1);
+    }
+
+    #[test]
+    fn test_write_conllu_overlap() {
+        let mut corpus = read_corpus_from_conllu_string(CONLLU).unwrap();
+        corpus.documents[1].1.content.insert(TEXT_LAYER.to_string(),
+            Layer::Characters("Hello world".to_string()));
+        let columns = ConlluColumns::guess(&corpus.meta);
+        assert!(matches!(write_corpus_to_conllu_string(&corpus, &columns),
+            Err(TeangaError::ConlluExportError(ref id, _)) if *id == corpus.documents[1].0));
+        corpus.documents[1].1.content.insert(TOKENS_LAYER.to_string(),
+            Layer::SpanNoData(vec![(0, 5), (3, 11)]));
+        corpus.documents[1].1.content.insert(DEP_LAYER.to_string(),
+            Layer::Seq(vec![Data::TypedLink(0, "root".to_string()), Data::TypedLink(0, "dep".to_string())]));
+        corpus.documents[1].1.content.insert(LEMMA_LAYER.to_string(),
+            Layer::Seq(vec![Data::String("hello".to_string()), Data::String("world".to_string())]));
+        corpus.documents[1].1.content.insert(UPOS_LAYER.to_string(),
+            Layer::Seq(vec![Data::String("INTJ".to_string()), Data::String("NOUN".to_string())]));
+        corpus.documents[1].1.content.insert(FEATS_LAYER.to_string(),
+            Layer::Seq(vec![Data::String("_".to_string()), Data::String("_".to_string())]));
+        match write_corpus_to_conllu_string(&corpus, &columns) {
+            Err(TeangaError::ConlluExportError(_, msg)) => assert!(msg.contains("overlaps")),
+            r => panic!("Unexpected result {:?}", r)
+        }
+    }
+
+    #[test]
+    fn test_write_conllu_tab() {
+        let mut corpus = read_corpus_from_conllu_string(CONLLU).unwrap();
+        corpus.documents[1].1.content.insert(TEXT_LAYER.to_string(),
+            Layer::Characters("He\tlo".to_string()));
+        let columns = ConlluColumns::guess(&corpus.meta);
+        match write_corpus_to_conllu_string(&corpus, &columns) {
+            Err(TeangaError::ConlluExportError(_, msg)) => assert!(msg.contains("tab")),
+            r => panic!("Unexpected result {:?}", r)
+        }
+    }
+
+    #[test]
+    fn test_read_conllu_bad_head() {
+        let result = read_corpus_from_conllu_string("1\tHello\t_\t_\t_\t_\t2\troot\t_\t_\n");
+        assert!(matches!(result, Err(TeangaError::ConlluError(1, _))));
+        let result = read_corpus_from_conllu_string("1\tHello\t_\t_\t_\t_\t0\troot\t_\t_\n2\tthere\t_\t_\t_\t_\t_\t_\t_\t_\n");
+        assert!(matches!(result, Err(TeangaError::ConlluError(2, _))));
+    }
+
+    #[test]
+    fn test_read_conllu_unparsed() {
+        let corpus = read_corpus_from_conllu_string("1\tHello\thello\t_\t_\t_\t_\t_\t_\t_\n2\tthere\tthere\t_\t_\t_\t_\t_\t_\t_\n").unwrap();
+        assert!(!corpus.meta.contains_key(DEP_LAYER));
+        let doc = &corpus.documents[0].1;
+        assert!(!doc.content.contains_key(DEP_LAYER));
+        assert_eq!(doc.content[TOKENS_LAYER], Layer::SpanNoData(vec![(0, 5), (6, 11)]));
+        let columns = ConlluColumns::guess(&corpus.meta);
+        assert_eq!(columns.head, None);
+        let conllu = write_corpus_to_conllu_string(&corpus, &columns).unwrap();
+        assert!(conllu.contains("2\tthere\tthere\t_\t_\t_\t_\t_\t_\t_\n"));
+    }
+}
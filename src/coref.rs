@@ -0,0 +1,82 @@
+//! Coreference chains built from link layers. Each annotation of a link layer
+//! is a mention that links to its antecedent, and the mentions joined by
+//! links form a cluster.
+use std::collections::HashMap;
+use crate::arcs::{ArcDiagram, Item, Word};
+use crate::render::{COLORS, N_COLORS};
+
+#[derive(Debug, Clone, PartialEq)]
+/// The mentions that refer to the same entity
+pub struct Cluster {
+    /// The mentions in text order
+    pub mentions : Vec<Word>,
+    pub color : &'static str,
+}
+
+/// Group the words of a diagram into clusters by following their links. The
+/// clusters are in order of their first mention.
+pub fn clusters(diagram : &ArcDiagram) -> Vec<Cluster> {
+    fn find(parent : &mut [usize], k : usize) -> usize {
+        let mut root = k;
+        while parent[root] != root {
+            root = parent[root];
+        }
+        let mut k = k;
+        while parent[k] != root {
+            let next = parent[k];
+            parent[k] = root;
+            k = next;
+        }
+        root
+    }
+    let mut parent : Vec<usize> = (0..diagram.words.len()).collect();
+    for arc in diagram.arcs.iter() {
+        let head = find(&mut parent, arc.head);
+        let dependent = find(&mut parent, arc.dependent);
+        parent[dependent] = head;
+    }
+    let mut index = HashMap::new();
+    let mut clusters : Vec<Cluster> = Vec::new();
+    for (k, word) in diagram.words.iter().enumerate() {
+        let root = find(&mut parent, k);
+        let c = *index.entry(root).or_insert_with(|| {
+            clusters.push(Cluster { mentions: Vec::new(), color: COLORS[clusters.len() % N_COLORS] });
+            clusters.len() - 1
+        });
+        clusters[c].mentions.push(word.clone());
+    }
+    clusters
+}
+
+/// The colour of the cluster of each mention, by the item it is
+pub fn mention_colors(clusters : &[Cluster]) -> HashMap<Item, &'static str> {
+    clusters.iter()
+        .flat_map(|cluster| cluster.mentions.iter().map(|word| (word.item.clone(), cluster.color)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arcs::link_arcs;
+    use crate::serialization::read_corpus_from_json_string;
+
+    #[test]
+    fn test_clusters() {
+        // "Síle" starts a chain and "leabhar" refers only to itself
+        let corpus = read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"mentions\":{\"type\":\"span\",\"on\":\"text\"},
+\"coref\":{\"type\":\"seq\",\"on\":\"mentions\",\"data\":\"link\"}},
+\"Kjco\":{\"text\":\"Síle said she lost her leabhar\",
+\"mentions\":[[0,4],[10,13],[19,22],[23,30]],\"coref\":[0,0,1,3]}}").unwrap();
+        let diagram = link_arcs(&corpus.documents[0].1, "coref", &corpus.meta).unwrap();
+        let clusters = clusters(&diagram);
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].mentions.iter().map(|word| word.text.as_str()).collect::<Vec<_>>(),
+            vec!["Síle", "she", "her"]);
+        assert_eq!(clusters[1].mentions, vec![Word { text: "leabhar".to_string(), start: 23, end: 30, item: ("mentions".to_string(), 3) }]);
+        let colors = mention_colors(&clusters);
+        assert_eq!(colors[&("mentions".to_string(), 2)], COLORS[0]);
+        assert_eq!(colors[&("mentions".to_string(), 3)], COLORS[1]);
+    }
+}
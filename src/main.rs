@@ -1,12 +1,28 @@
 use yew::prelude::*;
 use yew_icons::{Icon, IconId};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::{Blob, BlobPropertyBag, File, FileReader, HtmlAnchorElement, HtmlInputElement, HtmlSelectElement, HtmlTextAreaElement, Url};
 
 mod teanga;
 mod serialization;
 mod render;
+mod conllu;
+mod brat;
+mod text;
+mod validate;
+mod offsets;
+mod arcs;
+mod coref;
 
-use teanga::LayerType;
+use teanga::{IdProblem, LayerType};
+use serialization::{Format, QuarantinedDocument};
+use conllu::ConlluColumns;
+use brat::BratLayers;
+use validate::Diagnostic;
 
 #[derive(Clone, PartialEq, Properties)]
 pub struct Layer {
@@ -17,9 +33,21 @@ pub struct Layer {
 
 #[derive(Clone, PartialEq, Properties)]
 pub struct DocumentViewProps {
-    pub meta: HashMap<String, teanga::LayerDesc>,
-    pub document: teanga::Document,
-    pub layers: Vec<Layer>,
+    pub layout: Rc<render::DocLayout>,
+    pub layers: Rc<Vec<Layer>>,
+    /// Whether to keep grapheme clusters whole when an annotation splits one
+    pub whole_graphemes: bool,
+    /// Whether to draw the labels of seq layers on characters compactly
+    pub compact_chars: bool,
+    /// The link layers whose arcs are not drawn
+    pub hidden_arcs: Rc<HashSet<String>>,
+    /// The link layer shown as coreference chains
+    pub coref_layer: Option<String>,
+    /// The layers that are not enum layers but are coloured by value
+    pub value_layers: Rc<HashSet<String>>,
+    /// The colours chosen for values, by layer and value
+    pub custom_colors: Rc<HashMap<(String, String), &'static str>>,
+    pub on_recolor: Callback<(String, String, &'static str)>,
     pub on_next_doc: Callback<String>,
     pub on_prev_doc: Callback<String>,
 }
@@ -27,6 +55,22 @@ pub struct DocumentViewProps {
 fn DocumentView(props : &DocumentViewProps) -> Html {
     let on_next_doc = props.on_next_doc.clone();
     let on_prev_doc = props.on_prev_doc.clone();
+    // The annotation under the pointer, by section
+    let hovered = use_state(|| None::<(String, render::Hovered)>);
+    // The coreference cluster whose mentions are highlighted
+    let selected_cluster = use_state(|| None::<usize>);
+    {
+        let hovered = hovered.clone();
+        let selected_cluster = selected_cluster.clone();
+        use_effect_with(props.layout.clone(), move |_| {
+            hovered.set(None);
+            selected_cluster.set(None);
+        });
+    }
+    let coref = props.coref_layer.as_ref()
+        .and_then(|layer| props.layout.arcs.iter().find(|diagram| &diagram.layer == layer));
+    let clusters = coref.map(coref::clusters).unwrap_or_default();
+    let mention_colors = coref::mention_colors(&clusters);
     html! {
         <div class="p-4 flex flex-row h-full">
             <div class="basis-1">
@@ -35,25 +79,104 @@ fn DocumentView(props : &DocumentViewProps) -> Html {
             <div class="grow">
                 <h2 class="text-xl font-bold">{ "Document" }</h2>
                 {{
-                    match props.document.get_annos(&props.meta)  {
-                        Ok(docsecs) => {
-                            docsecs.iter().map(|(name, docsec)| {
+                    let layout = &props.layout;
+                    let compact : HashSet<&str> = props.layers.iter()
+                        .filter(|layer| props.compact_chars && layer.selected && layout.char_seqs.contains(&layer.name))
+                        .map(|layer| layer.name.as_str())
+                        .collect();
+                    let by_value : HashSet<&str> = props.layers.iter()
+                        .filter(|layer| layer.selected && (layout.enums.contains_key(&layer.name) || props.value_layers.contains(&layer.name)))
+                        .map(|layer| layer.name.as_str())
+                        .chain(compact.iter().copied())
+                        .collect();
+                    html! {
+                        <>
+                        { layout.sections.iter().map(|(name, docsec)| {
+                            let values = render::value_colors(docsec, &by_value, &layout.enums, &props.custom_colors);
+                            let counts = render::value_counts(docsec, &by_value);
+                            let empty = render::SectionLinks::default();
+                            let links = layout.links.get(name).unwrap_or(&empty);
+                            let mut highlight = match &*hovered {
+                                Some((section, (layer, index))) if section == name => links.related(layer, *index),
+                                _ => HashSet::new()
+                            };
+                            if let (Some(diagram), Some(cluster)) = (coref, selected_cluster.and_then(|c| clusters.get(c))) {
+                                if &diagram.on == name {
+                                    highlight.extend(cluster.mentions.iter().map(|word| word.item.clone()));
+                                }
+                            }
+                            let on_hover = {
+                                let hovered = hovered.clone();
+                                let name = name.clone();
+                                Callback::from(move |anno : Option<render::Hovered>| hovered.set(anno.map(|anno| (name.clone(), anno))))
+                            };
+                            let on_leave = {
+                                let on_hover = on_hover.clone();
+                                move |_ : MouseEvent| on_hover.emit(None)
+                            };
+                            let options = render::RenderOptions {
+                                layers: props.layers.iter()
+                                    .map(|x| (x.name.as_str(), x.selected || props.coref_layer.as_ref() == Some(&x.name)))
+                                    .collect(),
+                                defaults: &layout.defaults,
+                                whole_graphemes: props.whole_graphemes,
+                                values: &values,
+                                compact: &compact,
+                                links,
+                                highlight: &highlight,
+                                on_hover,
+                                coref_layer: props.coref_layer.as_deref(),
+                                clusters: &mention_colors
+                            };
+                            let arcs = layout.arcs.iter()
+                                .filter(|diagram| &diagram.on == name && !props.hidden_arcs.contains(&diagram.layer))
+                                .map(|diagram| {
+                                    let color = props.layers.iter().find(|layer| layer.name == diagram.layer)
+                                        .map_or("gray", |layer| layer.color.as_str());
+                                    html! { <div class="mb-2">{ arcs::render_arcs(diagram, color) }</div> }
+                                }).collect::<Html>();
+                            html! {
+                                <div class="p-4">
+                                    <h3 class="font-semibold mb-4">{ name }</h3>
+                                    <div class="overflow-x-auto">{ arcs }</div>
+                                    <div class="text-sm font-medium bg-bwhite border border-gray-400 rounded-md"
+                                        onmouseover={on_leave.clone()} onmouseleave={on_leave}>
+                                        { render::render_annos(docsec, &options) }
+                                    </div>
+                                    <div class="text-xs mt-2">{ render::value_legend(&values, &counts, &layout.enums, props.on_recolor.clone()) }</div>
+                                </div>
+                            }
+                        }).collect::<Html>() }
+                        {
+                            if layout.defaults.is_empty() {
+                                html! {}
+                            } else {
+                                let mut defaults : Vec<&String> = layout.defaults.iter().collect();
+                                defaults.sort();
                                 html! {
-                                    <div class="p-4">
-                                        <h3 class="font-semibold mb-4">{ name }</h3>
-                                        <div class="text-sm font-medium bg-bwhite border border-gray-400 rounded-md">
-                                            { render::render_annos(&docsec, props.layers.iter().map(|x| (x.name.as_str(), x.selected)).collect()) }
-                                        </div>
+                                    <div class="px-4 text-sm italic">
+                                        { format!("Default values (dashed): {}", defaults.into_iter().cloned().collect::<Vec<_>>().join(", ")) }
                                     </div>
                                 }
-                            }).collect::<Html>()
-                        }
-                        Err(e) => html! {
-                            <span>{ format!("Error: {}", e) }</span>
+                            }
                         }
+                        { layout.errors.iter().map(|(name, e)| html! {
+                            <div class="px-4 text-sm text-red-700">
+                                <span class="font-bold">{ name }</span>{ format!(": not shown ({})", e) }
+                            </div>
+                        }).collect::<Html>() }
+                        </>
                     }
                 }}
             </div>
+            {
+                if coref.is_some() {
+                    html! { <CorefClusters clusters={clusters.clone()} selected={*selected_cluster}
+                        on_select={let selected_cluster = selected_cluster.clone(); move |c| selected_cluster.set(c)}/> }
+                } else {
+                    html! {}
+                }
+            }
             <div class="basis-1">
                 <button class="button h-full" onclick={move |_| on_next_doc.emit("".to_string())}><Icon icon_id={IconId::BootstrapChevronCompactRight}/></button>
             </div>
@@ -61,9 +184,275 @@ fn DocumentView(props : &DocumentViewProps) -> Html {
     }
 }
 
+#[derive(Clone, PartialEq, Properties)]
+pub struct CorefClustersProps {
+    pub clusters: Vec<coref::Cluster>,
+    /// The index of the cluster whose mentions are highlighted
+    pub selected: Option<usize>,
+    pub on_select: Callback<Option<usize>>,
+}
+
+#[function_component]
+fn CorefClusters(props : &CorefClustersProps) -> Html {
+    html! {
+        <div class="basis-64 shrink-0 p-4">
+            <h3 class="font-semibold mb-4">{ format!("Clusters ({})", props.clusters.len()) }</h3>
+            <ul class="text-sm bg-bwhite border border-gray-400 rounded-md max-h-96 overflow-y-auto">
+                { props.clusters.iter().enumerate().map(|(c, cluster)| {
+                    let selected = props.selected == Some(c);
+                    let on_click = {
+                        let on_select = props.on_select.clone();
+                        move |_| on_select.emit(if selected { None } else { Some(c) })
+                    };
+                    let text = &cluster.mentions[0].text;
+                    html! {
+                        <li class={classes!("w-full", "border-b", "border-gray-400", "p-2", "cursor-pointer", "flex", "items-center",
+                            selected.then_some("bg-yellow-200"))} onclick={on_click}>
+                            <span class={classes!("inline-block", "w-3", "h-3", "me-2", "rounded-sm", format!("bg-{}-500", cluster.color))}></span>
+                            <span class="grow">{ text }</span>
+                            <span class="text-gray-500">{ cluster.mentions.len() }</span>
+                        </li>
+                    }
+                }).collect::<Html>() }
+            </ul>
+        </div>
+    }
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct ModalProps {
+    pub title: String,
+    pub open: bool,
+    pub on_close: Callback<MouseEvent>,
+    pub children: Html,
+}
+
+#[function_component]
+fn Modal(props : &ModalProps) -> Html {
+    let close1 = props.on_close.clone();
+    let close2 = props.on_close.clone();
+    html! {
+          <div class={{
+              if props.open {
+                  classes!("fixed", "w-full", "h-full", "top-0", "left-0", "flex", "items-center", "justify-center")
+              } else {
+                  classes!("opacity-0", "pointer-events-none", "fixed", "w-full", "h-full", "top-0", "left-0", "flex", "items-center", "justify-center")
+              }
+          }}>
+                <div class="modal-overlay absolute w-full h-full bg-gray-900 opacity-50"></div>
+
+                    <div class="modal-container bg-white w-11/12 md:max-w-md mx-auto rounded shadow-lg z-50 overflow-y-auto">
+
+                    <div class="modal-close absolute top-0 right-0 cursor-pointer flex flex-col items-center mt-4 mr-4 text-white text-sm z-50" onclick={close1}>
+                    <svg class="fill-current text-white" xmlns="http://www.w3.org/2000/svg" width="18" height="18" viewBox="0 0 18 18">
+                    <path d="M14.53 4.53l-1.06-1.06L9 7.94 4.53 3.47 3.47 4.53 7.94 9l-4.47 4.47 1.06 1.06L9 10.06l4.47 4.47 1.06-1.06L10.06 9z"></path>
+                    </svg>
+                    </div>
+
+                    <div class="modal-content py-4 text-left px-6">
+                    <div class="flex justify-between items-center pb-3">
+                    <p class="text-2xl font-bold">{ &props.title }</p>
+                    <div class="modal-close cursor-pointer z-50" onclick={close2}>
+                    <svg class="fill-current text-black" xmlns="http://www.w3.org/2000/svg" width="18" height="18" viewBox="0 0 18 18">
+                    <path d="M14.53 4.53l-1.06-1.06L9 7.94 4.53 3.47 3.47 4.53 7.94 9l-4.47 4.47 1.06 1.06L9 10.06l4.47 4.47 1.06-1.06L10.06 9z"></path>
+                    </svg>
+                    </div>
+                    </div>
+
+                    // The contents are only rendered while open, so inputs
+                    // such as file pickers start empty each time
+                    { if props.open { props.children.clone() } else { html! {} } }
+
+                    </div>
+                </div>
+            </div>
+    }
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct ModalErrorProps {
+    pub error: Option<String>,
+}
+
+#[function_component]
+fn ModalError(props : &ModalErrorProps) -> Html {
+    match props.error {
+        Some(ref e) => html! { <p class="text-sm text-red-700 mt-2">{ e }</p> },
+        None => html! {}
+    }
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct IdProblemsProps {
+    pub problems: Vec<IdProblem>,
+}
+
+#[function_component]
+fn IdProblems(props : &IdProblemsProps) -> Html {
+    if props.problems.is_empty() {
+        return html! {};
+    }
+    html! {
+        <div class="p-4">
+            <h3 class="font-semibold mb-4">{ "Document identifiers" }</h3>
+            <ul class="text-sm bg-bwhite border border-red-400 rounded-md">
+                { props.problems.iter().map(|problem| html! {
+                    <li class="w-full border-b border-gray-400 p-2 text-red-700">{ problem.to_string() }</li>
+                }).collect::<Html>() }
+            </ul>
+        </div>
+    }
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct DisplayOptionsProps {
+    pub whole_graphemes: bool,
+    pub on_whole_graphemes: Callback<bool>,
+    pub compact_chars: bool,
+    pub on_compact_chars: Callback<bool>,
+    /// The link layers, with whether their arcs are shown
+    pub arc_layers: Vec<(String, bool)>,
+    pub on_toggle_arcs: Callback<String>,
+    /// The link layer shown as coreference chains
+    pub coref_layer: Option<String>,
+    pub on_coref_layer: Callback<Option<String>>,
+    /// The layers with string data, with whether they are coloured by value
+    pub value_layers: Vec<(String, bool)>,
+    pub on_toggle_values: Callback<String>,
+}
+
+#[function_component]
+fn DisplayOptions(props : &DisplayOptionsProps) -> Html {
+    let on_whole_graphemes = props.on_whole_graphemes.clone();
+    let on_compact_chars = props.on_compact_chars.clone();
+    let on_coref_layer = props.on_coref_layer.clone();
+    html! {
+        <div class="p-4">
+            <h3 class="font-semibold mb-4">{ "Display" }</h3>
+            <label class="flex items-center text-sm font-medium">
+                <input type="checkbox" class="w-4 h-4 me-2" checked={props.whole_graphemes}
+                    onchange={move |e: Event| on_whole_graphemes.emit(e.target_unchecked_into::<HtmlInputElement>().checked())}/>
+                { "Keep characters whole" }
+            </label>
+            <label class="flex items-center text-sm font-medium mt-2">
+                <input type="checkbox" class="w-4 h-4 me-2" checked={props.compact_chars}
+                    onchange={move |e: Event| on_compact_chars.emit(e.target_unchecked_into::<HtmlInputElement>().checked())}/>
+                { "Compact character labels" }
+            </label>
+            { props.arc_layers.iter().map(|(layer, shown)| {
+                let on_toggle_arcs = props.on_toggle_arcs.clone();
+                html! {
+                    <label class="flex items-center text-sm font-medium mt-2">
+                        <input type="checkbox" class="w-4 h-4 me-2" checked={*shown}
+                            onchange={let layer = layer.clone(); move |_| on_toggle_arcs.emit(layer.clone())}/>
+                        { format!("Arcs for {}", layer) }
+                    </label>
+                }
+            }).collect::<Html>() }
+            { props.value_layers.iter().map(|(layer, by_value)| {
+                let on_toggle_values = props.on_toggle_values.clone();
+                html! {
+                    <label class="flex items-center text-sm font-medium mt-2">
+                        <input type="checkbox" class="w-4 h-4 me-2" checked={*by_value}
+                            onchange={let layer = layer.clone(); move |_| on_toggle_values.emit(layer.clone())}/>
+                        { format!("Colour {} by value", layer) }
+                    </label>
+                }
+            }).collect::<Html>() }
+            {
+                if props.arc_layers.is_empty() {
+                    html! {}
+                } else {
+                    html! {
+                        <label class="block text-sm font-medium mt-2">{ "Coreference chains" }
+                            <select class="block w-full text-sm border border-gray-400 rounded-md p-1 mt-1"
+                                onchange={move |e: Event| {
+                                    let layer = e.target_unchecked_into::<HtmlSelectElement>().value();
+                                    on_coref_layer.emit(if layer.is_empty() { None } else { Some(layer) })
+                                }}>
+                                <option value="" selected={props.coref_layer.is_none()}>{ "(none)" }</option>
+                                { props.arc_layers.iter().map(|(layer, _)| html! {
+                                    <option value={layer.clone()} selected={props.coref_layer.as_ref() == Some(layer)}>{ layer }</option>
+                                }).collect::<Html>() }
+                            </select>
+                        </label>
+                    }
+                }
+            }
+        </div>
+    }
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct DiagnosticsProps {
+    pub diagnostics: Vec<Diagnostic>,
+    pub on_select_doc: Callback<String>,
+}
+
+#[function_component]
+fn Diagnostics(props : &DiagnosticsProps) -> Html {
+    if props.diagnostics.is_empty() {
+        return html! {};
+    }
+    html! {
+        <div class="p-4">
+            <h3 class="font-semibold mb-4">{ format!("Validation ({} problems)", props.diagnostics.len()) }</h3>
+            <ul class="text-sm bg-bwhite border border-red-400 rounded-md max-h-64 overflow-y-auto">
+                { props.diagnostics.iter().map(|diagnostic| {
+                    let on_click = {
+                        let on_select_doc = props.on_select_doc.clone();
+                        let document = diagnostic.document.clone();
+                        move |_| if let Some(ref document) = document {
+                            on_select_doc.emit(document.clone())
+                        }
+                    };
+                    html! {
+                        <li class="w-full border-b border-gray-400 p-2 text-red-700 cursor-pointer" onclick={on_click}>
+                            { diagnostic.to_string() }
+                        </li>
+                    }
+                }).collect::<Html>() }
+            </ul>
+        </div>
+    }
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct QuarantineProps {
+    pub documents: Vec<QuarantinedDocument>,
+    pub error: Option<String>,
+    pub on_download: Callback<MouseEvent>,
+}
+
+#[function_component]
+fn Quarantine(props : &QuarantineProps) -> Html {
+    if props.documents.is_empty() {
+        return html! {};
+    }
+    html! {
+        <div class="p-4">
+            <div class="flex flex-row items-center mb-4">
+                <h3 class="font-semibold grow">{ format!("Skipped documents ({})", props.documents.len()) }</h3>
+                <button class="bg-blue-500 hover:bg-blue-700 text-white text-sm font-bold py-1 px-2 rounded inline-flex items-center"
+                    onclick={props.on_download.clone()}>
+                    <Icon icon_id={IconId::LucideSave} class={classes!("w-4", "h-4", "me-2")}/>{ "Download" }
+                </button>
+            </div>
+            <ul class="text-sm bg-bwhite border border-red-400 rounded-md max-h-64 overflow-y-auto">
+                { props.documents.iter().map(|doc| html! {
+                    <li class="w-full border-b border-gray-400 p-2 text-red-700">
+                        <span class="font-bold">{ &doc.id }</span>{ format!(": {}", doc.error) }
+                    </li>
+                }).collect::<Html>() }
+            </ul>
+            <ModalError error={props.error.clone()}/>
+        </div>
+    }
+}
+
 #[derive(Properties, Clone, PartialEq)]
 pub struct LayerSelectProps {
-    pub layers: Vec<Layer>,
+    pub layers: Rc<Vec<Layer>>,
     pub on_layer_enable: Callback<usize>,
 }
 
@@ -113,14 +502,187 @@ pub enum Msg {
     ToggleLayer(usize),
     NextDoc,
     PrevDoc,
+    GoToDoc(String),
     ToggleModal(&'static str),
+    SelectFiles(Vec<File>),
+    SetLoadText(String),
+    SetLoadFormat(Option<Format>),
+    SetLoadLenient(bool),
+    LoadFiles,
+    FilesLoaded(Result<Vec<(String, String)>, String>),
+    SetSaveFilename(String),
+    SetSaveFormat(Format),
+    SetSavePretty(bool),
+    SetConlluColumn(&'static str, Option<String>),
+    SaveFile,
+    SaveQuarantined,
+    SetWholeGraphemes(bool),
+    SetCompactChars(bool),
+    ToggleArcs(String),
+    SetCorefLayer(Option<String>),
+    ToggleValueColors(String),
+    SetValueColor(String, String, &'static str),
 }
 
 pub struct App {
     corpus: teanga::Corpus,
-    layers: Vec<Layer>,
+    layers: Rc<Vec<Layer>>,
+    id_problems: Vec<IdProblem>,
+    diagnostics: Vec<Diagnostic>,
+    doc_order: Vec<usize>,
+    /// The position of the current document in `doc_order`
     doc_no: usize,
     load_modal: bool,
+    load_files: Vec<File>,
+    load_text: String,
+    load_format: Option<Format>,
+    /// Whether to skip documents that cannot be read when loading
+    load_lenient: bool,
+    quarantined: Vec<QuarantinedDocument>,
+    quarantine_error: Option<String>,
+    load_error: Option<String>,
+    save_modal: bool,
+    save_filename: String,
+    save_format: Format,
+    /// Whether to write JSON with one line per layer rather than compactly
+    save_pretty: bool,
+    conllu_columns: ConlluColumns,
+    save_error: Option<String>,
+    /// Whether annotations that split a grapheme cluster are drawn around the
+    /// whole cluster
+    whole_graphemes: bool,
+    /// Whether the labels of seq layers on characters are drawn as coloured
+    /// underlines rather than one label per character
+    compact_chars: bool,
+    /// The layout of each document that has been shown, by its index in the
+    /// corpus, as identifiers may be shared. This is cleared when a new corpus
+    /// is loaded.
+    layouts: RefCell<HashMap<usize, Rc<render::DocLayout>>>,
+    /// The link layers whose arcs have been hidden
+    hidden_arcs: Rc<HashSet<String>>,
+    /// The link layer shown as coreference chains, if any
+    coref_layer: Option<String>,
+    /// The layers with string data that are coloured by value, as enum layers
+    /// always are
+    value_layers: Rc<HashSet<String>>,
+    /// The colours chosen for values, by layer and value
+    custom_colors: Rc<HashMap<(String, String), &'static str>>,
+}
+
+impl App {
+    /// The choice of layer for each column when saving as CoNLL-U
+    fn view_conllu_columns(&self, ctx: &Context<Self>) -> Html {
+        ConlluColumns::COLUMNS.iter().map(|column| {
+            let column = *column;
+            let chosen = self.conllu_columns.get(column);
+            let on_change = ctx.link().callback(move |e: Event| {
+                let select: HtmlSelectElement = e.target_unchecked_into();
+                Msg::SetConlluColumn(column, Some(select.value()).filter(|v| !v.is_empty()))
+            });
+            html! {
+                <div class="flex flex-row items-center mt-2">
+                    <label class="basis-1/3 text-sm font-medium">{ column.to_uppercase() }</label>
+                    <select class="grow text-sm border border-gray-400 rounded-md p-1" onchange={on_change}>
+                        <option value="" selected={chosen.is_none()}>{ "(none)" }</option>
+                        {
+                            ConlluColumns::candidates(&self.corpus.meta, column, &self.conllu_columns.tokens)
+                                .into_iter().map(|layer| html! {
+                                    <option selected={chosen == Some(&layer)} value={layer.clone()}>{ layer }</option>
+                                }).collect::<Html>()
+                        }
+                    </select>
+                </div>
+            }
+        }).collect::<Html>()
+    }
+
+    /// The layout of a document, which is computed the first time it is shown
+    fn layout(&self, doc : usize) -> Rc<render::DocLayout> {
+        let (_, document) = &self.corpus.documents[doc];
+        self.layouts.borrow_mut().entry(doc)
+            .or_insert_with(|| Rc::new(render::DocLayout::new(document, &self.corpus.meta)))
+            .clone()
+    }
+
+    /// Replace the current corpus and reset the view to the first document
+    fn set_corpus(&mut self, corpus: teanga::Corpus) {
+        self.corpus = corpus;
+        self.layers = Rc::new(corpus_layers(&self.corpus));
+        self.layouts.borrow_mut().clear();
+        self.hidden_arcs = Rc::new(HashSet::new());
+        self.coref_layer = None;
+        self.value_layers = Rc::new(HashSet::new());
+        self.custom_colors = Rc::new(HashMap::new());
+        self.id_problems = self.corpus.check_ids();
+        self.diagnostics = self.corpus.validate();
+        self.doc_order = self.corpus.doc_order();
+        self.conllu_columns = ConlluColumns::guess(&self.corpus.meta);
+        self.doc_no = 0;
+    }
+}
+
+/// Build the selectable layers (all non-character layers) of a corpus
+fn corpus_layers(corpus: &teanga::Corpus) -> Vec<Layer> {
+    corpus.meta.iter().filter(|l| l.1.layer_type != LayerType::Characters).enumerate().map(|(i, (name, _))| {
+        Layer {
+            name: name.clone(),
+            selected: false,
+            color: render::COLORS[i % render::COLORS.len()].to_string(),
+        }
+    }).collect()
+}
+
+/// Read a file as text in the background, sending the contents (or an
+/// error message) to the callback when the read is finished
+fn read_file(file: &File, callback: Callback<Result<String, String>>) -> Result<(), String> {
+    let reader = FileReader::new().map_err(|_| "Could not create a file reader".to_string())?;
+    let result_reader = reader.clone();
+    let onloadend = Closure::once_into_js(move || {
+        callback.emit(result_reader.result().ok()
+            .and_then(|result| result.as_string())
+            .ok_or_else(|| "Could not read the file as text".to_string()));
+    });
+    reader.set_onloadend(Some(onloadend.unchecked_ref()));
+    reader.read_as_text(file).map_err(|_| "Could not read the file".to_string())
+}
+
+/// Read several files as text, sending their names and contents to the
+/// callback once all of them have been read
+fn read_files(files: &[File], callback: Callback<Result<Vec<(String, String)>, String>>) -> Result<(), String> {
+    let results = Rc::new(RefCell::new(vec![None; files.len()]));
+    for (i, file) in files.iter().enumerate() {
+        let results = results.clone();
+        let callback = callback.clone();
+        let name = file.name();
+        read_file(file, Callback::from(move |content: Result<String, String>| {
+            results.borrow_mut()[i] = Some(content.map(|content| (name.clone(), content)));
+            if results.borrow().iter().all(Option::is_some) {
+                callback.emit(results.take().into_iter().flatten().collect());
+            }
+        }))?;
+    }
+    Ok(())
+}
+
+/// Offer some bytes to the user as a file download, using a Blob and a
+/// temporary object URL
+fn download_file(filename: &str, content: &[u8], mime_type: &str) -> Result<(), String> {
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(content));
+    let options = BlobPropertyBag::new();
+    options.set_type(mime_type);
+    let blob = Blob::new_with_u8_array_sequence_and_options(&parts, &options)
+        .map_err(|_| "Could not create the file".to_string())?;
+    let url = Url::create_object_url_with_blob(&blob)
+        .map_err(|_| "Could not create a URL for the file".to_string())?;
+    let anchor = web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.create_element("a").ok())
+        .and_then(|element| element.dyn_into::<HtmlAnchorElement>().ok())
+        .ok_or_else(|| "Could not create a download link".to_string())?;
+    anchor.set_href(&url);
+    anchor.set_download(filename);
+    anchor.click();
+    Url::revoke_object_url(&url).map_err(|_| "Could not release the file URL".to_string())
 }
 
 impl Component for App {
@@ -135,28 +697,80 @@ impl Component for App {
 \"Kjco\":{\"text\":\"This is a document.\",\"tokens\":[[0,4],[5,7],[8,9],[10,19]]
 ,\"pos\":[\"DT\",\"VBZ\",\"DT\",\"NN\"]},
 \"abcd\":{\"text\":\"This is a second document\"}}").unwrap(),
-            layers: Vec::new(),
+            layers: Rc::new(Vec::new()),
+            id_problems: Vec::new(),
+            diagnostics: Vec::new(),
+            doc_order: Vec::new(),
             doc_no: 0,
             load_modal: false,
+            load_files: Vec::new(),
+            load_text: String::new(),
+            load_format: None,
+            load_lenient: false,
+            quarantined: Vec::new(),
+            quarantine_error: None,
+            load_error: None,
+            save_modal: false,
+            save_filename: "corpus.json".to_string(),
+            save_format: Format::Json,
+            save_pretty: true,
+            conllu_columns: ConlluColumns::default(),
+            save_error: None,
+            whole_graphemes: true,
+            compact_chars: true,
+            layouts: RefCell::new(HashMap::new()),
+            hidden_arcs: Rc::new(HashSet::new()),
+            coref_layer: None,
+            value_layers: Rc::new(HashSet::new()),
+            custom_colors: Rc::new(HashMap::new()),
         };
-        app.layers = app.corpus.meta.iter().filter(|l| l.1.layer_type != LayerType::Characters).enumerate().map(|(i, (name, _))| {
-            Layer {
-                name: name.clone(),
-                selected: false,
-                color: render::COLORS[i % render::COLORS.len()].to_string(),
-            }
-        }).collect();
+        app.layers = Rc::new(corpus_layers(&app.corpus));
+        app.id_problems = app.corpus.check_ids();
+        app.diagnostics = app.corpus.validate();
+        app.doc_order = app.corpus.doc_order();
+        app.conllu_columns = ConlluColumns::guess(&app.corpus.meta);
         app
     }
 
-    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
             Msg::ToggleLayer(i) => {
-                self.layers[i].selected = !self.layers[i].selected;
+                let layer = &mut Rc::make_mut(&mut self.layers)[i];
+                layer.selected = !layer.selected;
+                true
+            },
+            Msg::SetWholeGraphemes(whole) => {
+                self.whole_graphemes = whole;
+                true
+            },
+            Msg::SetCompactChars(compact) => {
+                self.compact_chars = compact;
+                true
+            },
+            Msg::ToggleArcs(layer) => {
+                let hidden = Rc::make_mut(&mut self.hidden_arcs);
+                if !hidden.remove(&layer) {
+                    hidden.insert(layer);
+                }
+                true
+            },
+            Msg::SetCorefLayer(layer) => {
+                self.coref_layer = layer;
+                true
+            },
+            Msg::ToggleValueColors(layer) => {
+                let layers = Rc::make_mut(&mut self.value_layers);
+                if !layers.remove(&layer) {
+                    layers.insert(layer);
+                }
+                true
+            },
+            Msg::SetValueColor(layer, value, color) => {
+                Rc::make_mut(&mut self.custom_colors).insert((layer, value), color);
                 true
             },
             Msg::NextDoc => {
-                if self.doc_no < self.corpus.documents.len() - 1 {
+                if self.doc_no + 1 < self.doc_order.len() {
                     self.doc_no += 1;
                 }
                 true
@@ -167,8 +781,137 @@ impl Component for App {
                 }
                 true
             },
-            Msg::ToggleModal(_) => {
-                self.load_modal = !self.load_modal;
+            Msg::GoToDoc(id) => {
+                match self.doc_order.iter().position(|&i| self.corpus.documents[i].0 == id) {
+                    Some(doc_no) => {
+                        self.doc_no = doc_no;
+                        true
+                    },
+                    None => false
+                }
+            },
+            Msg::ToggleModal(modal) => {
+                match modal {
+                    "save" => {
+                        self.save_modal = !self.save_modal;
+                        self.save_error = None;
+                    },
+                    _ => {
+                        self.load_modal = !self.load_modal;
+                        self.load_error = None;
+                        self.load_files.clear();
+                        self.load_text.clear();
+                    }
+                }
+                true
+            },
+            Msg::SelectFiles(files) => {
+                self.load_files = files;
+                self.load_error = None;
+                true
+            },
+            Msg::SetLoadText(text) => {
+                self.load_text = text;
+                false
+            },
+            Msg::SetLoadFormat(format) => {
+                self.load_format = format;
+                false
+            },
+            Msg::SetLoadLenient(lenient) => {
+                self.load_lenient = lenient;
+                false
+            },
+            Msg::LoadFiles => {
+                if self.load_files.is_empty() && !self.load_text.trim().is_empty() {
+                    // Pasted text is loaded as a file without a name
+                    ctx.link().send_message(Msg::FilesLoaded(Ok(vec![(String::new(), self.load_text.clone())])));
+                } else if self.load_files.is_empty() {
+                    self.load_error = Some("Please select a file or paste some text to load".to_string());
+                } else if let Err(e) = read_files(&self.load_files, ctx.link().callback(Msg::FilesLoaded)) {
+                    self.load_error = Some(e);
+                }
+                true
+            },
+            Msg::FilesLoaded(Ok(files)) => {
+                let format = self.load_format.unwrap_or_else(|| Format::from_files(&files));
+                let result = if self.load_lenient {
+                    serialization::read_corpus_from_files_lenient(&files, format)
+                } else {
+                    serialization::read_corpus_from_files(&files, format).map(|corpus| (corpus, Vec::new()))
+                };
+                match result {
+                    Ok((corpus, quarantined)) => {
+                        self.set_corpus(corpus);
+                        self.quarantined = quarantined;
+                        self.quarantine_error = None;
+                        self.load_modal = false;
+                        self.load_error = None;
+                        self.load_files.clear();
+                        self.load_text.clear();
+                    },
+                    Err(e) => {
+                        self.load_error = Some(format!("Could not parse corpus as {}: {}", format, e));
+                    }
+                }
+                true
+            },
+            Msg::FilesLoaded(Err(e)) => {
+                self.load_error = Some(e);
+                true
+            },
+            Msg::SetSaveFilename(filename) => {
+                self.save_filename = filename;
+                false
+            },
+            Msg::SetSaveFormat(format) => {
+                // Only replace the extension if it is one we recognise
+                let stem = self.save_filename.rsplit_once('.')
+                    .filter(|(_, ext)| Format::from_filename(&self.save_filename).is_some()
+                        || *ext == Format::Brat.extension())
+                    .map_or(self.save_filename.as_str(), |(stem, _)| stem);
+                self.save_filename = format!("{}.{}", stem, format.extension());
+                self.save_format = format;
+                true
+            },
+            Msg::SetSavePretty(pretty) => {
+                self.save_pretty = pretty;
+                false
+            },
+            Msg::SetConlluColumn(column, layer) => {
+                self.conllu_columns.set(column, layer);
+                true
+            },
+            Msg::SaveFile => {
+                let content = match self.save_format {
+                    Format::Conllu => conllu::write_corpus_to_conllu_string(&self.corpus, &self.conllu_columns)
+                        .map(String::into_bytes),
+                    Format::Brat => brat::write_corpus_to_brat(&self.corpus, &BratLayers::guess(&self.corpus.meta))
+                        .and_then(|docs| brat::write_brat_zip(&docs)),
+                    Format::Json if self.save_pretty => serialization::write_corpus_to_json_string_pretty(&self.corpus)
+                        .map(String::into_bytes),
+                    format => serialization::write_corpus_to_string(&self.corpus, format)
+                        .map(String::into_bytes)
+                };
+                let result = content
+                    .map_err(|e| format!("Could not serialize corpus: {}", e))
+                    .and_then(|content| download_file(&self.save_filename, &content, self.save_format.mime_type()));
+                match result {
+                    Ok(()) => {
+                        self.save_modal = false;
+                        self.save_error = None;
+                    },
+                    Err(e) => {
+                        self.save_error = Some(e);
+                    }
+                }
+                true
+            },
+            Msg::SaveQuarantined => {
+                let result = serialization::write_quarantined_to_json_string(&self.corpus.meta, &self.quarantined)
+                    .map_err(|e| format!("Could not serialize the skipped documents: {}", e))
+                    .and_then(|content| download_file("skipped.json", content.as_bytes(), Format::Json.mime_type()));
+                self.quarantine_error = result.err();
                 true
             }
         }
@@ -178,10 +921,72 @@ impl Component for App {
         let on_layer_enable = ctx.link().callback(Msg::ToggleLayer);
         let next_doc = ctx.link().callback(|_:String| Msg::NextDoc);
         let prev_doc = ctx.link().callback(|_:String| Msg::PrevDoc);
+        let go_to_doc = ctx.link().callback(Msg::GoToDoc);
         let toggle_modal1 = ctx.link().callback(Msg::ToggleModal);
         let toggle_modal2 = ctx.link().callback(Msg::ToggleModal);
-        let toggle_modal3 = ctx.link().callback(Msg::ToggleModal);
-        let toggle_modal4 = ctx.link().callback(Msg::ToggleModal);
+        let close_load = ctx.link().callback(|_| Msg::ToggleModal("load"));
+        let close_save = ctx.link().callback(|_| Msg::ToggleModal("save"));
+        let select_file = ctx.link().callback(|e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            Msg::SelectFiles(input.files()
+                .map(|files| (0..files.length()).filter_map(|i| files.get(i)).collect())
+                .unwrap_or_default())
+        });
+        let set_load_format = ctx.link().callback(|e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            Msg::SetLoadFormat(match select.value().as_str() {
+                "json" => Some(Format::Json),
+                "yaml" => Some(Format::Yaml),
+                "conllu" => Some(Format::Conllu),
+                "brat" => Some(Format::Brat),
+                "text" => Some(Format::Text),
+                _ => None
+            })
+        });
+        let set_load_text = ctx.link().callback(|e: Event| {
+            let input: HtmlTextAreaElement = e.target_unchecked_into();
+            Msg::SetLoadText(input.value())
+        });
+        let load_file = ctx.link().callback(|_| Msg::LoadFiles);
+        let set_save_filename = ctx.link().callback(|e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            Msg::SetSaveFilename(input.value())
+        });
+        let set_save_format = ctx.link().callback(|e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            Msg::SetSaveFormat(match select.value().as_str() {
+                "yaml" => Format::Yaml,
+                "conllu" => Format::Conllu,
+                "brat" => Format::Brat,
+                _ => Format::Json
+            })
+        });
+        let save_file = ctx.link().callback(|_| Msg::SaveFile);
+        let set_save_pretty = ctx.link().callback(|e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            Msg::SetSavePretty(input.checked())
+        });
+        let save_quarantined = ctx.link().callback(|_| Msg::SaveQuarantined);
+        let set_load_lenient = ctx.link().callback(|e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            Msg::SetLoadLenient(input.checked())
+        });
+        let set_whole_graphemes = ctx.link().callback(Msg::SetWholeGraphemes);
+        let set_compact_chars = ctx.link().callback(Msg::SetCompactChars);
+        let toggle_arcs = ctx.link().callback(Msg::ToggleArcs);
+        let set_coref_layer = ctx.link().callback(Msg::SetCorefLayer);
+        let toggle_values = ctx.link().callback(Msg::ToggleValueColors);
+        let set_value_color = ctx.link().callback(|(layer, value, color)| Msg::SetValueColor(layer, value, color));
+        let mut value_layers : Vec<(String, bool)> = self.corpus.meta.iter()
+            .filter(|(_, desc)| desc.data == Some(teanga::DataType::String))
+            .map(|(name, _)| (name.clone(), self.value_layers.contains(name)))
+            .collect();
+        value_layers.sort();
+        let mut arc_layers : Vec<(String, bool)> = self.corpus.meta.iter()
+            .filter(|(_, desc)| arcs::is_link_layer(desc))
+            .map(|(name, _)| (name.clone(), !self.hidden_arcs.contains(name)))
+            .collect();
+        arc_layers.sort();
          html! { 
              <>
             <div class="flex flex-row min-h-screen">
@@ -190,6 +995,12 @@ impl Component for App {
                         <h1 class="font-bold">{ "Teanga Corpus Viewer" }</h1>
                     </div>
                     <LayerSelect on_layer_enable={on_layer_enable.clone()} layers={self.layers.clone()}/>
+                    <DisplayOptions whole_graphemes={self.whole_graphemes} on_whole_graphemes={set_whole_graphemes}
+                        compact_chars={self.compact_chars} on_compact_chars={set_compact_chars}
+                        arc_layers={arc_layers} on_toggle_arcs={toggle_arcs}
+                        coref_layer={self.coref_layer.clone()} on_coref_layer={set_coref_layer}
+                        value_layers={value_layers} on_toggle_values={toggle_values}/>
+                    <IdProblems problems={self.id_problems.clone()}/>
 
                     <div class="p-4 flex flex-col">
                         <button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded m-2 inline-flex items-center"
@@ -199,60 +1010,83 @@ impl Component for App {
                         <button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded m-2 inline-flex items-center">
                             <Icon icon_id={IconId::OcticonsBeaker24} class={classes!("w-4", "h-4", "me-2")}/>{ "Analyse" } 
                         </button>
-                        <button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded m-2 inline-flex items-center">
+                        <button class="bg-blue-500 hover:bg-blue-700 text-white font-bold py-2 px-4 rounded m-2 inline-flex items-center"
+                        onclick={move |_| toggle_modal2.emit("save")}>
                             <Icon icon_id={IconId::LucideSave} class={classes!("w-4", "h-4", "me-2")}/>{ "Save" }
                         </button>
                     </div>
                 </div>
                 <div class="bg-gray-100 grow">
                     { 
-                        if self.corpus.documents.len() > 0 { 
+                        if !self.corpus.documents.is_empty() { 
                             html! { <DocumentView 
-                                meta={self.corpus.meta.clone()}
-                                layers={self.layers.clone()} document={self.corpus.documents[self.doc_no].1.clone()}
+                                layout={self.layout(self.doc_order[self.doc_no])}
+                                layers={self.layers.clone()}
+                                whole_graphemes={self.whole_graphemes} compact_chars={self.compact_chars}
+                                hidden_arcs={self.hidden_arcs.clone()}
+                                coref_layer={self.coref_layer.clone()}
+                                value_layers={self.value_layers.clone()}
+                                custom_colors={self.custom_colors.clone()} on_recolor={set_value_color}
                         on_next_doc={next_doc} on_prev_doc={prev_doc}/> }
                         } else {
                             html! { <p>{ "No documents loaded" }</p> }
                         }
                     }
+                    <Quarantine documents={self.quarantined.clone()} error={self.quarantine_error.clone()} on_download={save_quarantined}/>
+                    <Diagnostics diagnostics={self.diagnostics.clone()} on_select_doc={go_to_doc}/>
                 </div>
             </div>
-          <div class={{
-              if self.load_modal {
-                  classes!("fixed", "w-full", "h-full", "top-0", "left-0", "flex", "items-center", "justify-center")
-              } else {
-                  classes!("opacity-0", "pointer-events-none", "fixed", "w-full", "h-full", "top-0", "left-0", "flex", "items-center", "justify-center")
-              }
-          }}>
-                <div class="modal-overlay absolute w-full h-full bg-gray-900 opacity-50"></div>
-
-                    <div class="modal-container bg-white w-11/12 md:max-w-md mx-auto rounded shadow-lg z-50 overflow-y-auto">
-
-                    <div class="modal-close absolute top-0 right-0 cursor-pointer flex flex-col items-center mt-4 mr-4 text-white text-sm z-50" onclick={move |_| toggle_modal2.emit("load")}>
-                    <svg class="fill-current text-white" xmlns="http://www.w3.org/2000/svg" width="18" height="18" viewBox="0 0 18 18">
-                    <path d="M14.53 4.53l-1.06-1.06L9 7.94 4.53 3.47 3.47 4.53 7.94 9l-4.47 4.47 1.06 1.06L9 10.06l4.47 4.47 1.06-1.06L10.06 9z"></path>
-                    </svg>
-                    </div>
-
-                    <div class="modal-content py-4 text-left px-6">
-                    <div class="flex justify-between items-center pb-3">
-                    <p class="text-2xl font-bold">{ "Load a corpus" }</p>
-                    <div class="modal-close cursor-pointer z-50" onclick={move |_| toggle_modal3.emit("load")}>
-                    <svg class="fill-current text-black" xmlns="http://www.w3.org/2000/svg" width="18" height="18" viewBox="0 0 18 18">
-                    <path d="M14.53 4.53l-1.06-1.06L9 7.94 4.53 3.47 3.47 4.53 7.94 9l-4.47 4.47 1.06 1.06L9 10.06l4.47 4.47 1.06-1.06L10.06 9z"></path>
-                    </svg>
-                    </div>
-                    </div>
-
-                    <p>{ "Modal content can go here" }</p>
-
-                    <div class="flex justify-end pt-2">
-                    <button class="modal-close px-4 bg-indigo-500 p-3 rounded-lg text-white hover:bg-indigo-400" onclick={move |_| toggle_modal4.emit("load")}>{ "Load" }</button>
-                    </div>
-
-                    </div>
+            <Modal title="Load a corpus" open={self.load_modal} on_close={close_load}>
+                <input type="file" multiple=true accept=".json,.yaml,.yml,.conllu,.conll,.txt,.ann,application/json,application/yaml" class="block w-full text-sm" onchange={select_file}/>
+                <label class="block text-sm font-medium mt-2 mb-2">{ "Or paste some text" }</label>
+                <textarea class="block w-full h-32 text-sm border border-gray-400 rounded-md p-2"
+                    value={self.load_text.clone()} onchange={set_load_text}/>
+                <label class="block text-sm font-medium mt-2 mb-2">{ "Format" }</label>
+                <select class="block w-full text-sm border border-gray-400 rounded-md p-2" onchange={set_load_format}>
+                    <option value="auto" selected={self.load_format.is_none()}>{ "Detect automatically" }</option>
+                    <option value="json" selected={self.load_format == Some(Format::Json)}>{ "JSON" }</option>
+                    <option value="yaml" selected={self.load_format == Some(Format::Yaml)}>{ "YAML" }</option>
+                    <option value="conllu" selected={self.load_format == Some(Format::Conllu)}>{ "CoNLL-U" }</option>
+                    <option value="brat" selected={self.load_format == Some(Format::Brat)}>{ "brat (.txt and .ann files)" }</option>
+                    <option value="text" selected={self.load_format == Some(Format::Text)}>{ "Plain text" }</option>
+                </select>
+                <label class="flex items-center text-sm font-medium mt-2">
+                    <input type="checkbox" class="w-4 h-4 me-2" checked={self.load_lenient} onchange={set_load_lenient}/>
+                    { "Skip documents that cannot be read" }
+                </label>
+                <ModalError error={self.load_error.clone()}/>
+                <div class="flex justify-end pt-2">
+                    <button class="px-4 bg-indigo-500 p-3 rounded-lg text-white hover:bg-indigo-400" onclick={load_file}>{ "Load" }</button>
                 </div>
-            </div>
+            </Modal>
+            <Modal title="Save the corpus" open={self.save_modal} on_close={close_save}>
+                <label class="block text-sm font-medium mb-2">{ "File name" }</label>
+                <input type="text" class="block w-full text-sm border border-gray-400 rounded-md p-2"
+                    value={self.save_filename.clone()} onchange={set_save_filename}/>
+                <label class="block text-sm font-medium mt-2 mb-2">{ "Format" }</label>
+                <select class="block w-full text-sm border border-gray-400 rounded-md p-2" onchange={set_save_format}>
+                    <option value="json" selected={self.save_format == Format::Json}>{ "JSON" }</option>
+                    <option value="yaml" selected={self.save_format == Format::Yaml}>{ "YAML" }</option>
+                    <option value="conllu" selected={self.save_format == Format::Conllu}>{ "CoNLL-U" }</option>
+                    <option value="brat" selected={self.save_format == Format::Brat}>{ "brat (a .zip of .txt and .ann files)" }</option>
+                </select>
+                {
+                    match self.save_format {
+                        Format::Conllu => self.view_conllu_columns(ctx),
+                        Format::Json => html! {
+                            <label class="flex items-center text-sm font-medium mt-2">
+                                <input type="checkbox" class="w-4 h-4 me-2" checked={self.save_pretty} onchange={set_save_pretty}/>
+                                { "One line per layer (for version control)" }
+                            </label>
+                        },
+                        _ => html! {}
+                    }
+                }
+                <ModalError error={self.save_error.clone()}/>
+                <div class="flex justify-end pt-2">
+                    <button class="px-4 bg-indigo-500 p-3 rounded-lg text-white hover:bg-indigo-400" onclick={save_file}>{ "Save" }</button>
+                </div>
+            </Modal>
         </>
          }
     }
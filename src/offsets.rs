@@ -0,0 +1,126 @@
+//! Conversion between the offsets used in Teanga and other string offsets
+//!
+//! Offsets into a characters layer count Unicode code points, as in Python
+//! Teanga. Rust strings are indexed by UTF-8 bytes and the browser by UTF-16
+//! code units, so these helpers convert between the three. An offset equal to
+//! the length of the string is valid and refers to its end.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The length of a string in code points
+pub fn char_len(s : &str) -> usize {
+    s.chars().count()
+}
+
+/// The UTF-8 byte offset of a code point offset, or `None` if the offset is
+/// past the end of the string
+pub fn char_to_byte(s : &str, index : usize) -> Option<usize> {
+    s.char_indices().map(|(i, _)| i).chain(std::iter::once(s.len())).nth(index)
+}
+
+/// The code point offset of a UTF-8 byte offset, or `None` if the offset is
+/// past the end of the string or inside a character
+pub fn byte_to_char(s : &str, index : usize) -> Option<usize> {
+    if s.is_char_boundary(index) {
+        Some(char_len(&s[..index]))
+    } else {
+        None
+    }
+}
+
+/// The UTF-16 code unit offset of a code point offset, or `None` if the
+/// offset is past the end of the string
+#[allow(dead_code)]
+pub fn char_to_utf16(s : &str, index : usize) -> Option<usize> {
+    let mut units = 0;
+    let mut chars = s.chars();
+    for _ in 0..index {
+        units += chars.next()?.len_utf16();
+    }
+    Some(units)
+}
+
+/// The code point offset of a UTF-16 code unit offset, or `None` if the
+/// offset is past the end of the string or between the halves of a
+/// surrogate pair
+pub fn utf16_to_char(s : &str, index : usize) -> Option<usize> {
+    let mut units = 0;
+    for (i, c) in s.chars().enumerate() {
+        if units == index {
+            return Some(i);
+        } else if units > index {
+            return None;
+        }
+        units += c.len_utf16();
+    }
+    if units == index {
+        Some(char_len(s))
+    } else {
+        None
+    }
+}
+
+/// The part of a string between two code point offsets
+pub fn char_slice(s : &str, start : usize, end : usize) -> Option<&str> {
+    let from = char_to_byte(s, start)?;
+    let to = from + char_to_byte(&s[from..], end.checked_sub(start)?)?;
+    Some(&s[from..to])
+}
+
+/// The code point offsets at which grapheme clusters (extended, as in
+/// UAX #29) start, followed by the length of the string
+pub fn grapheme_boundaries(s : &str) -> Vec<usize> {
+    let mut boundaries = vec![0];
+    let mut offset = 0;
+    for g in s.graphemes(true) {
+        offset += char_len(g);
+        boundaries.push(offset);
+    }
+    boundaries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT : &str = "Tá mé ag foghlaim 中文 👋🏽!";
+
+    #[test]
+    fn test_char_offsets() {
+        assert_eq!(char_len(TEXT), 24);
+        assert_eq!(char_to_byte(TEXT, 2), Some(3));
+        assert_eq!(char_to_byte(TEXT, 24), Some(TEXT.len()));
+        assert_eq!(char_to_byte(TEXT, 25), None);
+        assert_eq!(byte_to_char(TEXT, 3), Some(2));
+        assert_eq!(byte_to_char(TEXT, 2), None);
+        assert_eq!(char_slice(TEXT, 18, 20), Some("中文"));
+        assert_eq!(char_slice(TEXT, 21, 23), Some("👋🏽"));
+        assert_eq!(char_slice(TEXT, 23, 21), None);
+        assert_eq!(char_slice(TEXT, 21, 25), None);
+    }
+
+    #[test]
+    fn test_utf16_offsets() {
+        // Each emoji is a surrogate pair in UTF-16
+        assert_eq!(char_to_utf16(TEXT, 21), Some(21));
+        assert_eq!(char_to_utf16(TEXT, 22), Some(23));
+        assert_eq!(char_to_utf16(TEXT, 24), Some(26));
+        assert_eq!(char_to_utf16(TEXT, 25), None);
+        assert_eq!(utf16_to_char(TEXT, 23), Some(22));
+        assert_eq!(utf16_to_char(TEXT, 22), None);
+        assert_eq!(utf16_to_char(TEXT, 26), Some(24));
+        assert_eq!(utf16_to_char(TEXT, 27), None);
+        for i in 0..=char_len(TEXT) {
+            assert_eq!(utf16_to_char(TEXT, char_to_utf16(TEXT, i).unwrap()), Some(i));
+            assert_eq!(byte_to_char(TEXT, char_to_byte(TEXT, i).unwrap()), Some(i));
+        }
+    }
+
+    #[test]
+    fn test_grapheme_boundaries() {
+        // "e" with a combining acute accent, and a family emoji joined by ZWJ
+        let s = "de\u{301}\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}!";
+        assert_eq!(grapheme_boundaries(s), vec![0, 1, 3, 8, 9]);
+        assert_eq!(grapheme_boundaries(""), vec![0]);
+    }
+}
@@ -1,50 +1,187 @@
-/// Code for rendering the annotations
+//! Code for rendering the annotations
 
 use yew::prelude::*;
-use crate::teanga::{DocSecs, Data, Anno};
-use std::collections::HashMap;
+use web_sys::HtmlSelectElement;
+use crate::teanga::{DocSecs, Data, DataType, Anno, Document, LayerDesc, LayerType};
+use crate::serialization::TeangaError;
+use crate::arcs::{ArcDiagram, Item, is_link_layer, link_arcs};
+use std::collections::{HashMap, HashSet};
+use crate::offsets::{char_len, char_slice, char_to_byte, grapheme_boundaries};
 
 pub const N_COLORS : usize = 17;
-pub const COLORS : [&'static str; 17] = [
+pub const COLORS : [&str; 17] = [
     "red", "lime", "cyan", "violet",
     "orange", "green", "sky", "purple",
     "amber", "emerald", "blue", "fuchsia",
     "yellow", "teal", "indigo", "pink", "rose" ];
 
+/// The annotations of a document laid out for display. Computing this is the
+/// expensive part of showing a document, so it is computed once and shared.
+/// Layouts are compared by identity, as a new layout is only made when the
+/// document or the metadata changes.
+pub struct DocLayout {
+    /// The laid out annotations of each character layer, by layer name
+    pub sections : Vec<(String, DocSecs)>,
+    /// The layers that could not be laid out, by layer name
+    pub errors : Vec<(String, TeangaError)>,
+    /// The layers filled in from their default
+    pub defaults : HashSet<String>,
+    /// The seq layers that are directly on a character layer
+    pub char_seqs : HashSet<String>,
+    /// The values of each enum layer, in the order they are declared
+    pub enums : HashMap<String, Vec<String>>,
+    /// The arc diagram of each link layer, in order of layer name
+    pub arcs : Vec<ArcDiagram>,
+    /// The links between the annotations of each character layer
+    pub links : HashMap<String, SectionLinks>,
+}
+
+impl DocLayout {
+    pub fn new(doc : &Document, meta : &HashMap<String, LayerDesc>) -> DocLayout {
+        let (sections, errors) = doc.get_annos(meta);
+        let mut sections : Vec<(String, DocSecs)> = sections.into_iter().collect();
+        sections.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut errors : Vec<(String, TeangaError)> = errors.into_iter().collect();
+        let mut link_layers : Vec<&String> = meta.iter()
+            .filter(|(name, desc)| is_link_layer(desc) && doc.content.contains_key(*name))
+            .map(|(name, _)| name)
+            .collect();
+        link_layers.sort();
+        let mut arcs = Vec::new();
+        for name in link_layers {
+            match link_arcs(doc, name, meta) {
+                Ok(diagram) => arcs.push(diagram),
+                Err(e) => if !errors.iter().any(|(n, _)| n == name) {
+                    errors.push((format!("{} (arcs)", name), e));
+                }
+            }
+        }
+        errors.sort_by(|a, b| a.0.cmp(&b.0));
+        let char_seqs = meta.iter()
+            .filter(|(_, desc)| desc.layer_type == LayerType::Seq
+                && meta.get(&desc.on).is_some_and(|on| on.layer_type == LayerType::Characters))
+            .map(|(name, _)| name.clone())
+            .collect();
+        let enums = meta.iter()
+            .filter_map(|(name, desc)| match &desc.data {
+                Some(DataType::Enum(values)) => Some((name.clone(), values.clone())),
+                _ => None
+            })
+            .collect();
+        let mut links : HashMap<String, SectionLinks> = HashMap::new();
+        for diagram in arcs.iter() {
+            links.entry(diagram.on.clone()).or_default().add(diagram);
+        }
+        DocLayout { sections, errors, defaults: doc.defaults.clone(), char_seqs, enums, arcs, links }
+    }
+}
+
+impl PartialEq for DocLayout {
+    fn eq(&self, other : &DocLayout) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+/// The links between the annotations on a character layer, by the item that
+/// each annotation is
+pub struct SectionLinks {
+    /// The item that each annotation of a link layer is, by the layer and
+    /// index of the annotation
+    pub items : HashMap<Item, Item>,
+    /// The item that each annotation of a link layer links to, with its
+    /// offsets
+    pub targets : HashMap<Item, (Item, usize, usize)>,
+    /// The items that link to each item
+    pub backlinks : HashMap<Item, Vec<Item>>,
+}
+
+impl SectionLinks {
+    /// Add the links of a link layer, as given by its arcs
+    fn add(&mut self, diagram : &ArcDiagram) {
+        for (k, arc) in diagram.arcs.iter().enumerate() {
+            let dependent = &diagram.words[arc.dependent];
+            let head = &diagram.words[arc.head];
+            let anno = (diagram.layer.clone(), k);
+            self.items.insert(anno.clone(), dependent.item.clone());
+            self.targets.insert(anno, (head.item.clone(), head.start, head.end));
+            if arc.head != arc.dependent {
+                self.backlinks.entry(head.item.clone()).or_default().push(dependent.item.clone());
+            }
+        }
+    }
+
+    /// The item that an annotation is
+    pub fn item(&self, layer : &str, index : usize) -> Item {
+        let anno = (layer.to_string(), index);
+        self.items.get(&anno).cloned().unwrap_or(anno)
+    }
+
+    /// The items related by links to an annotation: the item it links to and
+    /// the items that link to it
+    pub fn related(&self, layer : &str, index : usize) -> HashSet<Item> {
+        let mut related : HashSet<Item> = self.backlinks.get(&self.item(layer, index))
+            .into_iter().flatten().cloned().collect();
+        if let Some((target, _, _)) = self.targets.get(&(layer.to_string(), index)) {
+            related.insert(target.clone());
+        }
+        related
+    }
+}
+
+/// An annotation that the pointer is over, as its layer and index
+pub type Hovered = (String, usize);
+
 /// An iterator over a string that returns substrings of the string
 /// that follow unicode code points
 struct UniStrIter<'a> {
     str : &'a str,
-    chars : std::str::CharIndices<'a>, 
-    i : usize
+    /// The byte offset of the next character
+    i : usize,
+    /// The code point offset of the next character
+    pos : usize,
+    /// If set, the grapheme cluster boundaries that the string may be split at
+    boundaries : Option<Vec<usize>>
 }
 
 impl<'a> UniStrIter<'a> {
     /// Create a new UniStrIter from a string slice
     fn from_str(s : &'a str) -> UniStrIter<'a> {
-        let mut chars = s.char_indices();
-        chars.next();
-        UniStrIter { str: s, chars, i: 0 }
+        UniStrIter { str: s, i: 0, pos: 0, boundaries: None }
+    }
+
+    /// Create a new UniStrIter that only splits the string between grapheme
+    /// clusters
+    fn graphemes(s : &'a str) -> UniStrIter<'a> {
+        UniStrIter { str: s, i: 0, pos: 0, boundaries: Some(grapheme_boundaries(s)) }
     }
 
     /// Return the next n UniCode characters as a string
     fn next(&mut self, n : usize) -> Result<&'a str, &'static str> {
-        if n == 0 {
-            return Ok(&self.str[self.i..self.i]);
-        }
-        if n > 1 {
-            self.chars.nth(n - 2).ok_or("String too short")?;
-        }
-        if let Some((j, _)) = self.chars.next() {
-            let s = &self.str[self.i..j];
-            self.i = j;
-            return Ok(s);
-        } else if self.i < self.str.len() {
-            let s = &self.str[self.i..self.str.len()];
-            self.i = self.str.len();
-            return Ok(s);
-        } else {
-            return Err("String too short");
+        let rest = &self.str[self.i..];
+        let j = char_to_byte(rest, n).ok_or("String too short")?;
+        self.i += j;
+        self.pos += n;
+        Ok(&rest[..j])
+    }
+
+    /// Return the string up to the code point offset `j`. When splitting by
+    /// grapheme clusters, `j` is moved forward to the end of its cluster and
+    /// nothing is returned if the string has already been read past it.
+    fn to(&mut self, j : usize) -> &'a str {
+        let j = match &self.boundaries {
+            Some(b) => b.get(b.partition_point(|k| *k < j)).copied().unwrap_or(j),
+            None => j
+        };
+        self.next_or_rest(j.saturating_sub(self.pos))
+    }
+
+    /// Return the next n UniCode characters as a string, or the rest of the
+    /// string if it is shorter
+    fn next_or_rest(&mut self, n : usize) -> &'a str {
+        match self.next(n) {
+            Ok(s) => s,
+            Err(_) => self.rest()
         }
     }
 
@@ -52,74 +189,255 @@ impl<'a> UniStrIter<'a> {
     fn rest(&mut self) -> &'a str {
         let s = &self.str[self.i..];
         self.i = self.str.len();
+        self.pos += char_len(s);
         s
     }
 }
 
-pub fn render_annos(docsec : &DocSecs, enabled_layers : Vec<(&str,bool)>) -> Html {
+/// The colour of each value of the layers coloured by value, by layer and value
+pub type ValueColors<'a> = HashMap<(&'a str, &'a str), &'static str>;
+
+/// Count the annotations with each value of the layers in `layers`
+pub fn value_counts<'a>(docsec : &'a DocSecs, layers : &HashSet<&str>) -> HashMap<(&'a str, &'a str), usize> {
+    fn collect<'a>(annos : &'a [Anno], layers : &HashSet<&str>, counts : &mut HashMap<(&'a str, &'a str), usize>) {
+        for anno in annos {
+            // Pieces of an annotation split by the layout are counted once
+            if let (true, true, Some(Data::String(s))) = (anno.left_complete, layers.contains(&*anno.layer_name), &anno.data) {
+                *counts.entry((&anno.layer_name, s.as_str())).or_default() += 1;
+            }
+            collect(&anno.children, layers, counts);
+        }
+    }
+    let mut counts = HashMap::new();
+    collect(&docsec.annos, layers, &mut counts);
+    counts
+}
+
+/// Assign a colour to each value of the layers in `layers` that occur in a
+/// section. All the values of an enum layer are coloured in the order they
+/// are declared, so their colours are the same in every document, and other
+/// values are coloured in sorted order. Colours in `custom` replace the
+/// assigned ones.
+pub fn value_colors<'a>(docsec : &'a DocSecs, layers : &HashSet<&str>, enums : &'a HashMap<String, Vec<String>>,
+    custom : &HashMap<(String, String), &'static str>) -> ValueColors<'a> {
+    let mut values : Vec<(&str, &str)> = value_counts(docsec, layers).into_keys().collect();
+    values.sort();
+    let mut colors = HashMap::new();
+    let mut k = 0;
+    for (n, (layer, value)) in values.iter().enumerate() {
+        if n == 0 || values[n - 1].0 != *layer {
+            k = 0;
+            for declared in enums.get(*layer).into_iter().flatten() {
+                colors.insert((*layer, declared.as_str()), COLORS[k % N_COLORS]);
+                k += 1;
+            }
+        }
+        colors.entry((*layer, *value)).or_insert_with(|| {
+            k += 1;
+            COLORS[(k - 1) % N_COLORS]
+        });
+    }
+    for ((layer, value), color) in colors.iter_mut() {
+        if let Some(c) = custom.get(&(layer.to_string(), value.to_string())) {
+            *color = c;
+        }
+    }
+    colors
+}
+
+/// Show the colour and number of annotations of each value of the layers
+/// coloured by value, with a choice of colour for each value. The values of
+/// an enum layer are listed in the order they are declared.
+pub fn value_legend(colors : &ValueColors, counts : &HashMap<(&str, &str), usize>, enums : &HashMap<String, Vec<String>>,
+    on_recolor : Callback<(String, String, &'static str)>) -> Html {
+    let mut values : Vec<(&(&str, &str), &&str)> = colors.iter().collect();
+    values.sort_by_key(|((layer, value), _)| (*layer,
+        enums.get(*layer).and_then(|declared| declared.iter().position(|v| v == value)).unwrap_or(usize::MAX), *value));
+    values.into_iter().map(|((layer, value), color)| {
+        let on_change = {
+            let on_recolor = on_recolor.clone();
+            let (layer, value) = (layer.to_string(), value.to_string());
+            move |e : Event| {
+                let chosen = e.target_unchecked_into::<HtmlSelectElement>().value();
+                if let Some(color) = COLORS.iter().find(|c| **c == chosen) {
+                    on_recolor.emit((layer.clone(), value.clone(), color));
+                }
+            }
+        };
+        html! {
+            <span class="inline-flex items-center me-3">
+                <span class={classes!("inline-block", "w-3", "h-3", "me-1", "rounded-sm", format!("bg-{}-500", color))}></span>
+                { format!("{}: {} ({})", layer, value, counts.get(&(*layer, *value)).copied().unwrap_or(0)) }
+                <select class="ms-1 text-xs border border-gray-400 rounded-md" onchange={on_change}>
+                    { COLORS.iter().map(|c| html! {
+                        <option value={*c} selected={c == color}>{ *c }</option>
+                    }).collect::<Html>() }
+                </select>
+            </span>
+        }
+    }).collect()
+}
+
+/// How to render the annotations of a section
+pub struct RenderOptions<'a> {
+    /// The selectable layers in order, with whether each is shown
+    pub layers : Vec<(&'a str, bool)>,
+    /// The layers that come from the layer's default, which are drawn with a
+    /// dashed border
+    pub defaults : &'a HashSet<String>,
+    /// Whether annotation boundaries that fall inside a grapheme cluster are
+    /// moved to the end of the cluster
+    pub whole_graphemes : bool,
+    /// The colours of the values of the layers coloured by value
+    pub values : &'a ValueColors<'a>,
+    /// The layers whose values are drawn compactly as an underline rather
+    /// than as a label
+    pub compact : &'a HashSet<&'a str>,
+    /// The links used to show the text that a link points to
+    pub links : &'a SectionLinks,
+    /// The items of the annotations to highlight
+    pub highlight : &'a HashSet<Item>,
+    /// Called with the annotation that the pointer moves over
+    pub on_hover : Callback<Option<Hovered>>,
+    /// The layer shown as coreference chains, whose annotations are drawn in
+    /// the colour of their cluster in `clusters`
+    pub coref_layer : Option<&'a str>,
+    pub clusters : &'a HashMap<Item, &'static str>,
+}
+
+/// Render the annotations of a section
+pub fn render_annos(docsec : &DocSecs, options : &RenderOptions) -> Html {
     let mut layer_colors = HashMap::new();
-    let mut i = 0;
-    for (layer, include) in enabled_layers.iter() {
+    for (i, (layer, include)) in options.layers.iter().enumerate() {
         if *include {    
             layer_colors.insert(*layer, COLORS[i % N_COLORS]);
         }
-        i += 1;
     }
-    annos_to_html(&mut UniStrIter::from_str(docsec.content), &docsec.annos, 0, None, &layer_colors)
+    let mut content = if options.whole_graphemes {
+        UniStrIter::graphemes(&docsec.content)
+    } else {
+        UniStrIter::from_str(&docsec.content)
+    };
+    let style = Style { colors: layer_colors, text: &docsec.content, options };
+    annos_to_html(&mut content, &docsec.annos, 0, None, &style)
+}
+
+/// How the annotations of a section are drawn
+struct Style<'a> {
+    /// The colour of each enabled layer
+    colors : HashMap<&'a str, &'static str>,
+    /// The text of the section
+    text : &'a str,
+    options : &'a RenderOptions<'a>,
+}
+
+impl Style<'_> {
+    /// The label of a link: its type, if any, and the text it links to
+    fn link_label(&self, anno : &Anno, i : usize, link_type : Option<&str>) -> String {
+        let target = self.options.links.targets.get(&(anno.layer_name.to_string(), anno.index))
+            .and_then(|(_, start, end)| char_slice(self.text, *start, *end));
+        match (link_type, target) {
+            (Some(t), Some(target)) => format!("{} → {}", t, target),
+            (None, Some(target)) => format!("→ {}", target),
+            (Some(t), None) => format!("{}={}", t, i),
+            (None, None) => i.to_string()
+        }
+    }
+
+    /// Report the annotation when the pointer moves over it
+    fn on_hover(&self, anno : &Anno) -> Callback<MouseEvent> {
+        let on_hover = self.options.on_hover.clone();
+        let hovered = (anno.layer_name.to_string(), anno.index);
+        Callback::from(move |e : MouseEvent| {
+            e.stop_propagation();
+            on_hover.emit(Some(hovered.clone()));
+        })
+    }
+
+    /// The colour of the coreference cluster of an annotation, if it is in one
+    fn cluster_color(&self, anno : &Anno) -> Option<&'static str> {
+        if self.options.coref_layer == Some(&*anno.layer_name) {
+            self.options.clusters.get(&self.options.links.item(&anno.layer_name, anno.index)).copied()
+        } else {
+            None
+        }
+    }
+
+    /// The class that highlights an annotation, if it is highlighted
+    fn highlight(&self, anno : &Anno) -> Option<&'static str> {
+        self.options.highlight.contains(&self.options.links.item(&anno.layer_name, anno.index)).then_some("bg-yellow-200")
+    }
 }
 
-fn annos_to_html(content : &mut UniStrIter, annos : &Vec<Anno>, i : usize, j : Option<usize>,
-    colors : &HashMap<&str, &str>) -> Html {
+fn annos_to_html(content : &mut UniStrIter, annos : &[Anno], i : usize, j : Option<usize>,
+    style : &Style) -> Html {
     let mut html = Vec::new();
     let mut last_i = i;
     for anno in annos.iter() {
         if anno.start > last_i {
-            let text = content.next(anno.start - last_i).unwrap();
+            let text = content.to(anno.start);
             html.push(html! { {text} });
             last_i = anno.start;
         }
-        match colors.get(&anno.layer_name) {
-            Some(color) => {
-                let classes1 = classes!(format!("border-{}-900", color), "border-2", "rounded-md");
+        let value_color = match &anno.data {
+            Some(Data::String(s)) => style.options.values.get(&(&*anno.layer_name, s.as_str())).map(|c| (s, c)),
+            _ => None
+        };
+        match (style.colors.get(&*anno.layer_name), value_color) {
+            (Some(_), Some((value, value_color))) if style.options.compact.contains(&*anno.layer_name) => {
+                let border = if style.options.defaults.contains(&*anno.layer_name) { "border-dashed" } else { "border-solid" };
+                html.push(html! {
+                    <span class={classes!("border-b-4", border, format!("border-{}-500", value_color), style.highlight(anno))}
+                        title={format!("{}: {}", anno.layer_name, value)} onmouseover={style.on_hover(anno)}>
+                    { annos_to_html(content, &anno.children, last_i, Some(anno.end), style) }
+                    </span>
+                });
+            },
+            (Some(color), value_color) => {
+                let color = value_color.map_or(*color, |(_, c)| *c);
+                let color = style.cluster_color(anno).unwrap_or(color);
+                let border = if style.options.defaults.contains(&*anno.layer_name) { "border-dashed" } else { "border-solid" };
+                let classes1 = classes!(format!("border-{}-900", color), "border-2", border, "rounded-md", style.highlight(anno));
+                let on_hover = style.on_hover(anno);
                 let classes2 = classes!(format!("bg-{}-900", color), "text-white", "border-2", format!("border-{}-900", color), "rounded-t-md");
                 match anno.data {
                     None => html.push(html! {
-                        <span class={classes1}>
-                        { annos_to_html(content, &anno.children, last_i, Some(anno.end), &colors) }
+                        <span class={classes1} onmouseover={on_hover}>
+                        { annos_to_html(content, &anno.children, last_i, Some(anno.end), style) }
                         </span>
                     }),
                     Some(Data::String(ref s)) => {
                         html.push(html! { 
-                            <ruby class={classes1}>{ annos_to_html(content, &anno.children, last_i, Some(anno.end), &colors) }
+                            <ruby class={classes1} onmouseover={on_hover}>{ annos_to_html(content, &anno.children, last_i, Some(anno.end), style) }
                             <rt class={classes2}>{ s }</rt>
                         </ruby>
                         });
                     },
                     Some(Data::Link(ref i)) => {
                         html.push(html! {
-                            <ruby class={classes1}>{ annos_to_html(content, &anno.children, last_i, Some(anno.end), &colors) }
-                            <rt class={classes2}>{ i }</rt>
+                            <ruby class={classes1} onmouseover={on_hover}>{ annos_to_html(content, &anno.children, last_i, Some(anno.end), style) }
+                            <rt class={classes2}>{ style.link_label(anno, *i, None) }</rt>
                         </ruby>
                         });
                     },
                     Some(Data::TypedLink(ref i, ref s)) => {
                         html.push(html! {
-                            <ruby class={classes1}>{ annos_to_html(content, &anno.children, last_i, Some(anno.end), &colors) }
-                            <rt class={classes2}>{ s.to_owned() + "=" + &i.to_string() }</rt>
+                            <ruby class={classes1} onmouseover={on_hover}>{ annos_to_html(content, &anno.children, last_i, Some(anno.end), style) }
+                            <rt class={classes2}>{ style.link_label(anno, *i, Some(s)) }</rt>
                             </ruby>
                         });
                     }
                 }
             },
-            None => {
-                html.push(annos_to_html(content, &anno.children, last_i, Some(anno.end), &colors));
+            (None, _) => {
+                html.push(annos_to_html(content, &anno.children, last_i, Some(anno.end), style));
             }
         }
         last_i = anno.end;
     }
     if let Some(j) = j {
         if last_i < j {
-            let text = content.next(j - last_i).unwrap();
+            let text = content.to(j);
             html.push(html! { {text} });
         }
     } else {
@@ -146,5 +464,104 @@ mod tests {
         let mut s = UniStrIter::from_str("This");
         assert_eq!(s.next(4).unwrap(), "This");
     }
-}
 
+    #[test]
+    fn test_unistriter_multilingual() {
+        let mut s = UniStrIter::from_str("Dia duit 你好 👋🏽");
+        assert_eq!(s.next(9).unwrap(), "Dia duit ");
+        assert_eq!(s.next(2).unwrap(), "你好");
+        assert_eq!(s.next(0).unwrap(), "");
+        assert_eq!(s.next(1).unwrap(), " ");
+        assert!(s.next(3).is_err());
+        assert_eq!(s.next_or_rest(3), "👋🏽");
+        assert!(s.next(1).is_err());
+    }
+
+    #[test]
+    fn test_unistriter_graphemes() {
+        // Boundaries at 2 and 4 fall inside "é" and "👋🏽"
+        let text = "Cafe\u{301} 👋🏽!";
+        let mut s = UniStrIter::from_str(text);
+        assert_eq!(s.to(4), "Cafe");
+        assert_eq!(s.to(7), "\u{301} 👋");
+        let mut s = UniStrIter::graphemes(text);
+        assert_eq!(s.to(4), "Cafe\u{301}");
+        assert_eq!(s.to(5), "");
+        assert_eq!(s.to(7), " 👋🏽");
+        assert_eq!(s.rest(), "!");
+    }
+
+    #[test]
+    fn test_value_colors() {
+        let b = Some(Data::String("B".to_string()));
+        let i = Some(Data::String("I".to_string()));
+        let docsec = DocSecs {
+            content: "我爱北京".to_string(),
+            annos: vec![Anno::new("seg".into(), i.clone(), 0, 1), Anno::new("seg".into(), b.clone(), 1, 2),
+                Anno::new("seg".into(), i, 2, 3), Anno::new("pos".into(), b, 3, 4)]
+        };
+        let enums = HashMap::new();
+        let colors = value_colors(&docsec, &HashSet::from(["seg"]), &enums, &HashMap::new());
+        assert_eq!(colors.len(), 2);
+        assert_eq!(colors[&("seg", "B")], COLORS[0]);
+        assert_eq!(colors[&("seg", "I")], COLORS[1]);
+        let counts = value_counts(&docsec, &HashSet::from(["seg", "pos"]));
+        assert_eq!(counts[&("seg", "I")], 2);
+        assert_eq!(counts[&("pos", "B")], 1);
+    }
+
+    #[test]
+    fn test_value_colors_enum() {
+        let per = Some(Data::String("PER".to_string()));
+        let docsec = DocSecs {
+            content: "Síle in Gaillimh".to_string(),
+            annos: vec![Anno::new("ner".into(), per, 0, 4),
+                Anno::new("ner".into(), Some(Data::String("MISC".to_string())), 8, 16)]
+        };
+        // All declared values are coloured in order, then undeclared ones
+        let enums = HashMap::from([("ner".to_string(), vec!["PER".to_string(), "ORG".to_string(), "LOC".to_string()])]);
+        let colors = value_colors(&docsec, &HashSet::from(["ner"]), &enums, &HashMap::new());
+        assert_eq!(colors.len(), 4);
+        assert_eq!(colors[&("ner", "PER")], COLORS[0]);
+        assert_eq!(colors[&("ner", "LOC")], COLORS[2]);
+        assert_eq!(colors[&("ner", "MISC")], COLORS[3]);
+        let custom = HashMap::from([(("ner".to_string(), "PER".to_string()), "pink")]);
+        let colors = value_colors(&docsec, &HashSet::from(["ner"]), &enums, &custom);
+        assert_eq!(colors[&("ner", "PER")], "pink");
+        assert_eq!(colors[&("ner", "ORG")], COLORS[1]);
+    }
+
+    #[test]
+    fn test_doc_layout() {
+        let corpus = crate::serialization::read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"title\":{\"type\":\"characters\"},
+\"seg\":{\"type\":\"seq\",\"on\":\"text\",\"data\":\"string\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\"},
+\"pos\":{\"type\":\"seq\",\"on\":\"tokens\",\"data\":\"string\"}},
+\"KpDf\":{\"text\":\"我爱\",\"title\":\"Teideal\",\"seg\":[\"B\",\"I\"],\"tokens\":[[0,2]],\"pos\":[\"X\"]}}").unwrap();
+        let layout = DocLayout::new(&corpus.documents[0].1, &corpus.meta);
+        assert_eq!(layout.sections.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), vec!["text", "title"]);
+        assert!(layout.errors.is_empty());
+        assert_eq!(layout.char_seqs, HashSet::from(["seg".to_string()]));
+        assert!(layout != DocLayout::new(&corpus.documents[0].1, &corpus.meta));
+    }
+
+    #[test]
+    fn test_section_links() {
+        // Without a target, links index the tokens that the layer is on
+        let corpus = crate::serialization::read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\"},
+\"dep\":{\"type\":\"seq\",\"on\":\"tokens\",\"data\":\"link\"}},
+\"KpDf\":{\"text\":\"Dia duit\",\"tokens\":[[0,3],[4,8]],\"dep\":[1,1]}}").unwrap();
+        let layout = DocLayout::new(&corpus.documents[0].1, &corpus.meta);
+        assert!(layout.errors.is_empty());
+        let links = &layout.links["text"];
+        let token = |i : usize| ("tokens".to_string(), i);
+        assert_eq!(links.item("dep", 0), token(0));
+        assert_eq!(links.targets[&("dep".to_string(), 0)], (token(1), 4, 8));
+        assert_eq!(links.backlinks[&token(1)], vec![token(0)]);
+        assert_eq!(links.related("dep", 0), HashSet::from([token(1)]));
+        assert_eq!(links.related("dep", 1), HashSet::from([token(1), token(0)]));
+        assert_eq!(links.related("tokens", 1), HashSet::from([token(0)]));
+        assert!(links.related("tokens", 0).is_empty());
+    }
+}
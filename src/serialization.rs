@@ -1,14 +1,30 @@
 use thiserror::Error;
 use serde::{Serialize, Deserialize, Deserializer};
-use crate::teanga::{LayerDesc, LayerType, DataType, Layer, Data, Corpus, Document};
+use serde::de::{DeserializeSeed, MapAccess, SeqAccess};
+use std::marker::PhantomData;
+use crate::teanga::{LayerDesc, LayerType, DataType, Layer, Data, Corpus, Document, layer_order};
+use crate::conllu::{read_corpus_from_conllu_string, write_corpus_to_conllu_string, ConlluColumns};
+use crate::brat::{brat_documents, read_corpus_from_brat};
+use crate::text::read_corpus_from_texts;
 use serde::ser::{SerializeMap, Serializer, SerializeSeq};
 use serde::de::Visitor;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-struct TeangaVisitor();
+struct TeangaVisitor {
+    /// Set aside documents that cannot be read instead of failing
+    lenient: bool,
+}
+
+#[derive(Debug,Clone,PartialEq)]
+/// A document that could not be read in lenient mode, kept as it was found
+pub struct QuarantinedDocument {
+    pub id: String,
+    pub error: String,
+    pub value: serde_json::Value,
+}
 
 impl<'de> Visitor<'de> for TeangaVisitor {
-    type Value = Corpus;
+    type Value = (Corpus, Vec<QuarantinedDocument>);
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
         formatter.write_str("a string representing a corpus")
@@ -18,31 +34,122 @@ impl<'de> Visitor<'de> for TeangaVisitor {
         where A: serde::de::MapAccess<'de>
     {
         let mut corpus = Corpus::new();
+        let mut quarantined = Vec::new();
         while let Some(ref key) = map.next_key::<String>()? {
             if key == "_meta" {
                 let data = map.next_value::<HashMap<String, LayerDesc>>()?;
                 corpus.meta = data;
             } else if key == "_order" {
                 corpus.order = map.next_value::<Vec<String>>()?;
-            } else {
-                let doc = map.next_value::<HashMap<String, PyLayer>>()?;
-                let mut mapped_doc = HashMap::new();
-                for (id, layer) in &doc {
-                    let meta : &LayerDesc = corpus.meta.get(id).ok_or_else
-                        (|| serde::de::Error::custom(format!("No meta for layer {}", id)))?;
-                    mapped_doc.insert(id.clone(), Layer::from_py(layer.clone(), meta)
-                        .map_err(serde::de::Error::custom)?);
+            } else if self.lenient {
+                let value = map.next_value::<serde_json::Value>()?;
+                match (DocumentSeed { meta: &corpus.meta }).deserialize(value.clone()) {
+                    Ok(doc) => corpus.documents.push((key.clone(), doc)),
+                    Err(e) => quarantined.push(QuarantinedDocument {
+                        id: key.clone(),
+                        error: e.to_string(),
+                        value
+                    })
                 }
-                corpus.documents.push((key.clone(), Document { content: mapped_doc }));
+            } else {
+                let doc = map.next_value_seed(DocumentSeed { meta: &corpus.meta })?;
+                corpus.documents.push((key.clone(), doc));
+            }
+        }
+        // Set aside documents are not in the corpus, so they are left out of
+        // its order rather than reported as missing
+        let read : HashSet<&str> = corpus.documents.iter().map(|(id, _)| id.as_str()).collect();
+        let set_aside : HashSet<&str> = quarantined.iter().map(|q| q.id.as_str())
+            .filter(|id| !read.contains(id)).collect();
+        corpus.order.retain(|id| !set_aside.contains(id.as_str()));
+        Ok((corpus, quarantined))
+    }
+}
+
+/// Reads a document, using the metadata to decide the form of each layer
+struct DocumentSeed<'a> {
+    meta: &'a HashMap<String, LayerDesc>,
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for DocumentSeed<'a> {
+    type Value = Document;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where D: Deserializer<'de>
+    {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for DocumentSeed<'a> {
+    type Value = Document;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a map of layer names to layers")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+        where A: MapAccess<'de>
+    {
+        let mut doc = Document::new();
+        while let Some(name) = map.next_key::<String>()? {
+            let meta = self.meta.get(&name).ok_or_else(
+                || serde::de::Error::custom(TeangaError::MissingMeta(name.clone())))?;
+            let form = PyForm::for_layer(meta).map_err(|e| serde::de::Error::custom(
+                    format!("Layer {}: {}", name, e)))?;
+            let layer = map.next_value_seed(form).map_err(|e| serde::de::Error::custom(
+                    format!("Layer {}: {}", name, e)))?;
+            doc.content.insert(name, Layer::from_py(layer, meta).map_err(serde::de::Error::custom)?);
+        }
+        for (name, meta) in self.meta.iter() {
+            if let (Some(default), false) = (&meta.default, doc.content.contains_key(name)) {
+                let layer = PyForm::for_layer(meta)
+                    .and_then(|form| Ok(form.deserialize(default.clone())?))
+                    .and_then(|layer| Layer::from_py(layer, meta))
+                    .map_err(|e| serde::de::Error::custom(format!("Default of layer {}: {}", name, e)))?;
+                doc.content.insert(name.clone(), layer);
+                doc.defaults.insert(name.clone());
             }
         }
-        Ok(corpus)
+        Ok(doc)
+    }
+}
+
+/// Reads the items of a layer, reporting which item does not have the
+/// expected form
+struct ItemsVisitor<T> {
+    form: PyForm,
+    item: PhantomData<T>,
+}
+
+impl<'de, T : Deserialize<'de>> Visitor<'de> for ItemsVisitor<T> {
+    type Value = Vec<T>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "{}", self.form)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where A: SeqAccess<'de>
+    {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element::<T>().map_err(|e| serde::de::Error::custom(
+                format!("item {} should be {}: {}", items.len(), self.form.item(), e)))? {
+            items.push(item);
+        }
+        Ok(items)
     }
 }
 
 pub fn read_corpus_from_json_string(s: &str) -> Result<Corpus, serde_json::Error> {
     let mut deserializer = serde_json::Deserializer::from_str(s);
-    deserializer.deserialize_any(TeangaVisitor())
+    Ok(deserializer.deserialize_any(TeangaVisitor { lenient: false })?.0)
+}
+
+/// Read a corpus from JSON, setting aside any documents that cannot be read
+pub fn read_corpus_from_json_string_lenient(s: &str) -> Result<(Corpus, Vec<QuarantinedDocument>), serde_json::Error> {
+    let mut deserializer = serde_json::Deserializer::from_str(s);
+    deserializer.deserialize_any(TeangaVisitor { lenient: true })
 }
 
 pub fn write_corpus_to_json_string(corpus: &Corpus) -> Result<String, TeangaError> {
@@ -51,30 +158,319 @@ pub fn write_corpus_to_json_string(corpus: &Corpus) -> Result<String, TeangaErro
     Ok(String::from_utf8(ser.into_inner())?)
 }
 
+/// Write a corpus as JSON with one line for each layer of each document, so
+/// that changes to a corpus give small diffs
+pub fn write_corpus_to_json_string_pretty(corpus: &Corpus) -> Result<String, TeangaError> {
+    let mut ser = serde_json::Serializer::with_formatter(Vec::new(), LayerPerLineFormatter::new());
+    corpus.serialize(&mut ser)?;
+    Ok(String::from_utf8(ser.into_inner())?)
+}
+
+pub fn read_corpus_from_yaml_string(s: &str) -> Result<Corpus, serde_yaml::Error> {
+    Ok(serde_yaml::Deserializer::from_str(s).deserialize_any(TeangaVisitor { lenient: false })?.0)
+}
+
+/// Read a corpus from YAML, setting aside any documents that cannot be read
+pub fn read_corpus_from_yaml_string_lenient(s: &str) -> Result<(Corpus, Vec<QuarantinedDocument>), serde_yaml::Error> {
+    serde_yaml::Deserializer::from_str(s).deserialize_any(TeangaVisitor { lenient: true })
+}
+
+pub fn write_corpus_to_yaml_string(corpus: &Corpus) -> Result<String, TeangaError> {
+    Ok(serde_yaml::to_string(corpus)?)
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+/// A file format that a corpus can be stored in
+pub enum Format {
+    Json,
+    Yaml,
+    Conllu,
+    Brat,
+    Text,
+}
+
+impl Format {
+    /// Guess the format from the extension of a file name
+    pub fn from_filename(filename: &str) -> Option<Format> {
+        let (_, ext) = filename.rsplit_once('.')?;
+        match ext.to_lowercase().as_str() {
+            "json" => Some(Format::Json),
+            "yaml" | "yml" => Some(Format::Yaml),
+            "conllu" | "conll" => Some(Format::Conllu),
+            "ann" => Some(Format::Brat),
+            "txt" => Some(Format::Text),
+            _ => None
+        }
+    }
+
+    /// Guess the format from the content of a file. CoNLL-U is recognised
+    /// by a tab-separated first row and YAML by a `_meta` key, as a Teanga
+    /// corpus always has one. Anything else that does not start with a brace
+    /// is taken as plain text.
+    pub fn from_content(content: &str) -> Format {
+        let first_row = content.lines()
+            .find(|l| !l.trim().is_empty() && !l.starts_with('#'));
+        if content.trim_start().starts_with('{') {
+            Format::Json
+        } else if first_row.is_some_and(|l| l.split('\t').count() == 10) {
+            Format::Conllu
+        } else if content.lines().any(|l| l.starts_with("_meta:")) {
+            Format::Yaml
+        } else {
+            Format::Text
+        }
+    }
+
+    /// Guess the format of a set of files given by name and content. Any
+    /// `.ann` file makes it a brat corpus, otherwise the first file decides
+    pub fn from_files(files: &[(String, String)]) -> Format {
+        if files.iter().any(|(name, _)| Format::from_filename(name) == Some(Format::Brat)) {
+            Format::Brat
+        } else {
+            files.first().map_or(Format::Json, |(name, content)|
+                Format::from_filename(name).unwrap_or_else(|| Format::from_content(content)))
+        }
+    }
+
+    /// The usual file extension for this format when saving. brat corpora
+    /// are saved as a zip archive of their files.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Yaml => "yaml",
+            Format::Conllu => "conllu",
+            Format::Brat => "zip",
+            Format::Text => "txt",
+        }
+    }
+
+    /// The MIME type used when downloading a file in this format
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Yaml => "application/yaml",
+            Format::Brat => "application/zip",
+            Format::Conllu | Format::Text => "text/plain",
+        }
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Format::Json => write!(f, "JSON"),
+            Format::Yaml => write!(f, "YAML"),
+            Format::Conllu => write!(f, "CoNLL-U"),
+            Format::Brat => write!(f, "brat"),
+            Format::Text => write!(f, "plain text"),
+        }
+    }
+}
+
+pub fn read_corpus_from_string(s: &str, format: Format) -> Result<Corpus, TeangaError> {
+    match format {
+        Format::Json => Ok(read_corpus_from_json_string(s)?),
+        Format::Yaml => Ok(read_corpus_from_yaml_string(s)?),
+        Format::Conllu => read_corpus_from_conllu_string(s),
+        Format::Brat => Err(TeangaError::ModelError(
+            "A brat corpus must be read from .txt and .ann files".to_string())),
+        Format::Text => Ok(read_corpus_from_texts(&[s])),
+    }
+}
+
+/// Read a corpus from files given as pairs of file name and content. Only
+/// brat and plain text corpora can consist of more than one file, and each
+/// plain text file becomes a document.
+pub fn read_corpus_from_files(files: &[(String, String)], format: Format) -> Result<Corpus, TeangaError> {
+    match (format, files) {
+        (Format::Brat, _) => read_corpus_from_brat(&brat_documents(files)?),
+        (Format::Text, _) => Ok(read_corpus_from_texts(
+                &files.iter().map(|(_, content)| content).collect::<Vec<_>>())),
+        (_, [(_, content)]) => read_corpus_from_string(content, format),
+        _ => Err(TeangaError::ModelError(
+            format!("A {} corpus must be read from a single file", format)))
+    }
+}
+
+/// Read a corpus from files as `read_corpus_from_files` does, but set aside
+/// documents of a JSON or YAML corpus that cannot be read rather than
+/// failing. The other formats are read as usual.
+pub fn read_corpus_from_files_lenient(files: &[(String, String)], format: Format)
+    -> Result<(Corpus, Vec<QuarantinedDocument>), TeangaError> {
+    match (format, files) {
+        (Format::Json, [(_, content)]) => Ok(read_corpus_from_json_string_lenient(content)?),
+        (Format::Yaml, [(_, content)]) => Ok(read_corpus_from_yaml_string_lenient(content)?),
+        _ => Ok((read_corpus_from_files(files, format)?, Vec::new()))
+    }
+}
+
+/// Write documents that were set aside while reading as a JSON corpus with
+/// the metadata of the corpus they came from
+pub fn write_quarantined_to_json_string(meta: &HashMap<String, LayerDesc>,
+    docs: &[QuarantinedDocument]) -> Result<String, TeangaError> {
+    let mut ser = serde_json::Serializer::new(Vec::new());
+    let order : Vec<&String> = docs.iter().map(|doc| &doc.id).collect();
+    let mut map = ser.serialize_map(Some(docs.len() + 2))?;
+    map.serialize_entry("_meta", meta)?;
+    map.serialize_entry("_order", &order)?;
+    for doc in docs {
+        map.serialize_entry(&doc.id, &doc.value)?;
+    }
+    SerializeMap::end(map)?;
+    Ok(String::from_utf8(ser.into_inner())?)
+}
+
+pub fn write_corpus_to_string(corpus: &Corpus, format: Format) -> Result<String, TeangaError> {
+    match format {
+        Format::Json => write_corpus_to_json_string(corpus),
+        Format::Yaml => write_corpus_to_yaml_string(corpus),
+        Format::Conllu => write_corpus_to_conllu_string(corpus, &ConlluColumns::guess(&corpus.meta)),
+        Format::Brat => Err(TeangaError::ModelError(
+            "A brat corpus is written as a pair of files per document".to_string())),
+        Format::Text => Err(TeangaError::ModelError(
+            "Writing plain text is not supported".to_string())),
+    }
+}
+
 
 
 impl Serialize for Corpus {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where S: Serializer
     {
-        let mut map = serializer.serialize_map(Some(3))?;
-        map.serialize_entry("_meta", &self.meta)?;
-        map.serialize_entry("_order", &self.order)?;
-        for (id, doc) in &self.documents {
-            let mut mapped_doc = HashMap::new();
-            for (id, layer) in &doc.content {
-                let meta : &LayerDesc = self.meta.get(id).ok_or_else
-                    (|| serde::ser::Error::custom(format!("No meta for layer {}", id)))?;
-                mapped_doc.insert(id.clone(), layer.into_py(meta)
-                    .map_err(serde::ser::Error::custom)?);
+        // Write `_order` as it is shown, so that it always lists exactly the
+        // documents in the corpus. Layers are written in the canonical order
+        // so that saving the same corpus always gives the same output.
+        let doc_order = self.doc_order();
+        let order : Vec<&String> = doc_order.iter().map(|&i| &self.documents[i].0).collect();
+        let layer_order = layer_order(&self.meta);
+        let mut map = serializer.serialize_map(Some(self.documents.len() + 2))?;
+        map.serialize_entry("_meta", &OrderedMap(
+                layer_order.iter().map(|name| (*name, &self.meta[*name])).collect()))?;
+        map.serialize_entry("_order", &order)?;
+        for (id, doc) in doc_order.iter().map(|&i| &self.documents[i]) {
+            if let Some(name) = doc.content.keys().find(|name| !self.meta.contains_key(*name)) {
+                return Err(serde::ser::Error::custom(TeangaError::MissingMeta(name.clone())));
+            }
+            let mut mapped_doc = Vec::new();
+            for name in layer_order.iter().filter(|name| !doc.defaults.contains(**name)) {
+                if let Some(layer) = doc.content.get(*name) {
+                    mapped_doc.push((*name, layer.into_py(&self.meta[*name])
+                        .map_err(serde::ser::Error::custom)?));
+                }
             }
-            map.serialize_entry(id, &mapped_doc)?;
+            map.serialize_entry(id, &OrderedMap(mapped_doc))?;
         }
         map.end()
     }
 }
 
-#[derive(Debug,Clone,PartialEq,Serialize, Deserialize)]
+/// Entries that are serialized as a map in the order given
+struct OrderedMap<'a, V>(Vec<(&'a String, V)>);
+
+impl<'a, V : Serialize> Serialize for OrderedMap<'a, V> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in self.0.iter() {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+/// A JSON formatter that puts each layer of each document, each layer
+/// description and each id in `_order` on its own line, and writes everything
+/// below that compactly. This keeps the output short while a change to a layer
+/// only changes its line.
+struct LayerPerLineFormatter {
+    depth: usize,
+    has_value: bool,
+}
+
+impl LayerPerLineFormatter {
+    /// Containers up to this depth have one entry per line
+    const LINE_DEPTH : usize = 2;
+
+    fn new() -> Self {
+        LayerPerLineFormatter { depth: 0, has_value: false }
+    }
+
+    fn begin<W : ?Sized + std::io::Write>(&mut self, writer: &mut W, bracket: &[u8]) -> std::io::Result<()> {
+        self.depth += 1;
+        self.has_value = false;
+        writer.write_all(bracket)
+    }
+
+    fn end<W : ?Sized + std::io::Write>(&mut self, writer: &mut W, bracket: &[u8]) -> std::io::Result<()> {
+        self.depth -= 1;
+        if self.depth < Self::LINE_DEPTH && self.has_value {
+            self.newline(writer)?;
+        }
+        writer.write_all(bracket)
+    }
+
+    fn begin_entry<W : ?Sized + std::io::Write>(&mut self, writer: &mut W, first: bool) -> std::io::Result<()> {
+        if !first {
+            writer.write_all(b",")?;
+        }
+        if self.depth <= Self::LINE_DEPTH {
+            self.newline(writer)?;
+        }
+        Ok(())
+    }
+
+    fn newline<W : ?Sized + std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(b"\n")?;
+        for _ in 0..self.depth {
+            writer.write_all(b"  ")?;
+        }
+        Ok(())
+    }
+}
+
+impl serde_json::ser::Formatter for LayerPerLineFormatter {
+    fn begin_array<W : ?Sized + std::io::Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.begin(writer, b"[")
+    }
+
+    fn end_array<W : ?Sized + std::io::Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.end(writer, b"]")
+    }
+
+    fn begin_array_value<W : ?Sized + std::io::Write>(&mut self, writer: &mut W, first: bool) -> std::io::Result<()> {
+        self.begin_entry(writer, first)
+    }
+
+    fn end_array_value<W : ?Sized + std::io::Write>(&mut self, _writer: &mut W) -> std::io::Result<()> {
+        self.has_value = true;
+        Ok(())
+    }
+
+    fn begin_object<W : ?Sized + std::io::Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.begin(writer, b"{")
+    }
+
+    fn end_object<W : ?Sized + std::io::Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.end(writer, b"}")
+    }
+
+    fn begin_object_key<W : ?Sized + std::io::Write>(&mut self, writer: &mut W, first: bool) -> std::io::Result<()> {
+        self.begin_entry(writer, first)
+    }
+
+    fn begin_object_value<W : ?Sized + std::io::Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(if self.depth <= Self::LINE_DEPTH { b": " } else { b":" })
+    }
+
+    fn end_object_value<W : ?Sized + std::io::Write>(&mut self, _writer: &mut W) -> std::io::Result<()> {
+        self.has_value = true;
+        Ok(())
+    }
+}
+
+#[derive(Debug,Clone,PartialEq,Serialize)]
 #[serde(untagged)]
 enum PyLayer {
     CharacterLayer(String),
@@ -87,20 +483,127 @@ enum PyLayer {
     L3S(Vec<(usize,usize,usize,String)>),
 }
 
+impl PyLayer {
+    fn form(&self) -> PyForm {
+        match self {
+            PyLayer::CharacterLayer(_) => PyForm::CharacterLayer,
+            PyLayer::L1(_) => PyForm::L1,
+            PyLayer::L2(_) => PyForm::L2,
+            PyLayer::L3(_) => PyForm::L3,
+            PyLayer::LS(_) => PyForm::LS,
+            PyLayer::L1S(_) => PyForm::L1S,
+            PyLayer::L2S(_) => PyForm::L2S,
+            PyLayer::L3S(_) => PyForm::L3S,
+        }
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+/// The form of a `PyLayer`, which is decided by the layer description
+enum PyForm {
+    CharacterLayer,
+    L1,
+    L2,
+    L3,
+    LS,
+    L1S,
+    L2S,
+    L3S,
+}
+
+impl PyForm {
+    /// The form that a layer with this description is stored in
+    fn for_layer(meta : &LayerDesc) -> TeangaResult<PyForm> {
+        // Seq layers have no indices, div and element layers one and spans two
+        let indices = match meta.layer_type {
+            LayerType::Characters => return Ok(PyForm::CharacterLayer),
+            LayerType::Seq => 0,
+            LayerType::Div | LayerType::Element => 1,
+            LayerType::Span => 2,
+        };
+        match (indices, &meta.data) {
+            (0, None) => Err(TeangaError::ModelError(
+                "A seq layer must have a data type".to_string())),
+            (0, Some(DataType::String | DataType::Enum(_))) => Ok(PyForm::LS),
+            (0, Some(DataType::Link)) => Ok(PyForm::L1),
+            (0, Some(DataType::TypedLink(_))) => Ok(PyForm::L1S),
+            (1, None) => Ok(PyForm::L1),
+            (1, Some(DataType::String | DataType::Enum(_))) => Ok(PyForm::L1S),
+            (1, Some(DataType::Link)) => Ok(PyForm::L2),
+            (1, Some(DataType::TypedLink(_))) => Ok(PyForm::L2S),
+            (_, None) => Ok(PyForm::L2),
+            (_, Some(DataType::String | DataType::Enum(_))) => Ok(PyForm::L2S),
+            (_, Some(DataType::Link)) => Ok(PyForm::L3),
+            (_, Some(DataType::TypedLink(_))) => Ok(PyForm::L3S),
+        }
+    }
+
+    /// A description of one item of a layer in this form
+    fn item(&self) -> &'static str {
+        match self {
+            PyForm::CharacterLayer => "a character",
+            PyForm::L1 => "an index",
+            PyForm::L2 => "a pair of indices",
+            PyForm::L3 => "a triple of indices",
+            PyForm::LS => "a string",
+            PyForm::L1S => "an index and a string",
+            PyForm::L2S => "two indices and a string",
+            PyForm::L3S => "three indices and a string",
+        }
+    }
+}
+
+impl std::fmt::Display for PyForm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PyForm::CharacterLayer => write!(f, "a string"),
+            PyForm::L1 => write!(f, "a list of indices"),
+            PyForm::L2 => write!(f, "a list of pairs of indices"),
+            PyForm::L3 => write!(f, "a list of triples of indices"),
+            PyForm::LS => write!(f, "a list of strings"),
+            PyForm::L1S => write!(f, "a list of [index, string] items"),
+            PyForm::L2S => write!(f, "a list of [index, index, string] items"),
+            PyForm::L3S => write!(f, "a list of [index, index, index, string] items"),
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for PyForm {
+    type Value = PyLayer;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where D: Deserializer<'de>
+    {
+        fn items<'de, T : Deserialize<'de>, D : Deserializer<'de>>(form : PyForm, deserializer : D) -> Result<Vec<T>, D::Error> {
+            deserializer.deserialize_seq(ItemsVisitor { form, item: PhantomData })
+        }
+        match self {
+            PyForm::CharacterLayer => Ok(PyLayer::CharacterLayer(String::deserialize(deserializer)?)),
+            PyForm::L1 => Ok(PyLayer::L1(items(self, deserializer)?)),
+            PyForm::L2 => Ok(PyLayer::L2(items(self, deserializer)?)),
+            PyForm::L3 => Ok(PyLayer::L3(items(self, deserializer)?)),
+            PyForm::LS => Ok(PyLayer::LS(items(self, deserializer)?)),
+            PyForm::L1S => Ok(PyLayer::L1S(items(self, deserializer)?)),
+            PyForm::L2S => Ok(PyLayer::L2S(items(self, deserializer)?)),
+            PyForm::L3S => Ok(PyLayer::L3S(items(self, deserializer)?)),
+        }
+    }
+}
 
 impl Layer {
+    #[allow(clippy::wrong_self_convention)]
     fn into_py(&self, meta : &LayerDesc) -> TeangaResult<PyLayer> {
         match self {
             Layer::Characters(val) => Ok(PyLayer::CharacterLayer(val.clone())),
             Layer::Seq(val) => {
                 match meta.data {
                     None => Err(TeangaError::ModelError(
-                        format!("Layer contains data but not data type"))),
+                        "Layer contains data but not data type".to_string())),
                     Some(DataType::String) => {
                         let mut result = Vec::new();
                         for id in val {
                             result.push(id.clone().into_str().ok_or_else(|| TeangaError::ModelError(
-                                format!("String layer contains non-string data")))?);
+                                "String layer contains non-string data".to_string()))?);
                         }
                         Ok(PyLayer::LS(result))
                     },
@@ -108,7 +611,7 @@ impl Layer {
                         let mut result = Vec::new();
                         for id in val {
                             result.push(id.clone().into_str().ok_or_else(|| TeangaError::ModelError(
-                                format!("String layer contains non-string data")))?);
+                                "String layer contains non-string data".to_string()))?);
                         }
                         Ok(PyLayer::LS(result))
                     },
@@ -116,7 +619,7 @@ impl Layer {
                         let mut result = Vec::new();
                         for d in val {
                             result.push(d.clone().into_usize().ok_or_else(|| TeangaError::ModelError(
-                                format!("Link layer contains non-link data")))?);
+                                "Link layer contains non-link data".to_string()))?);
                         }
                         Ok(PyLayer::L1(result))
                     },
@@ -124,7 +627,7 @@ impl Layer {
                         let mut result = Vec::new();
                         for id in val {
                             result.push(id.clone().into_link().ok_or_else(|| TeangaError::ModelError(
-                                format!("Typed link layer contains non-link data")))?);
+                                "Typed link layer contains non-link data".to_string()))?);
                         }
                         Ok(PyLayer::L1S(result))
                     }
@@ -133,13 +636,13 @@ impl Layer {
             Layer::Div(val) => {
                 match meta.data {
                     None => Err(TeangaError::ModelError(
-                        format!("Layer contains data but no data type"))),
+                        "Layer contains data but no data type".to_string())),
                     Some(DataType::String) => {
                         let mut result = Vec::new();
                         for (start, data) in val {
                             result.push((*start, 
                                     data.clone().into_str().ok_or_else(|| TeangaError::ModelError(
-                                        format!("String layer contains non-string data")))?));
+                                        "String layer contains non-string data".to_string()))?));
                         }
                         Ok(PyLayer::L1S(result))
                     },
@@ -147,7 +650,7 @@ impl Layer {
                         let mut result = Vec::new();
                         for (start, data) in val {
                             result.push((*start, data.clone().into_str().ok_or_else(|| TeangaError::ModelError(
-                                format!("String layer contains non-string data")))?));
+                                "String layer contains non-string data".to_string()))?));
                         }
                         Ok(PyLayer::L1S(result))
                     },
@@ -156,7 +659,7 @@ impl Layer {
                         for (start, data) in val {
                             result.push((*start, 
                                     data.clone().into_usize().ok_or_else(|| TeangaError::ModelError(
-                                        format!("Link layer contains non-link data")))?));
+                                        "Link layer contains non-link data".to_string()))?));
                         }
                         Ok(PyLayer::L2(result))
                     },
@@ -164,7 +667,7 @@ impl Layer {
                         let mut result = Vec::new();
                         for (start, data) in val {
                             let tl = data.clone().into_link().ok_or_else(|| TeangaError::ModelError(
-                                format!("Typed link layer contains non-link data")))?;
+                                "Typed link layer contains non-link data".to_string()))?;
                             result.push((*start, tl.0, tl.1));
                         }
                         Ok(PyLayer::L2S(result))
@@ -174,12 +677,12 @@ impl Layer {
             Layer::Element(val) => {
                 match meta.data {
                     None => Err(TeangaError::ModelError(
-                        format!("Layer contains data but no data type"))),
+                        "Layer contains data but no data type".to_string())),
                     Some(DataType::String) => {
                         let mut result = Vec::new();
                         for (start, data) in val {
                             result.push((*start, data.clone().into_str().ok_or_else(|| TeangaError::ModelError(
-                                format!("String layer contains non-string data")))?));
+                                "String layer contains non-string data".to_string()))?));
                         }
                         Ok(PyLayer::L1S(result))
                     },
@@ -187,7 +690,7 @@ impl Layer {
                         let mut result = Vec::new();
                         for (start, data) in val {
                             result.push((*start, data.clone().into_str().ok_or_else(|| TeangaError::ModelError(
-                                format!("String layer contains non-string data")))?));
+                                "String layer contains non-string data".to_string()))?));
                         }
                         Ok(PyLayer::L1S(result))
                     },
@@ -195,7 +698,7 @@ impl Layer {
                         let mut result = Vec::new();
                         for (start, data) in val {
                             result.push((*start, data.clone().into_usize().ok_or_else(|| TeangaError::ModelError(
-                                format!("Link layer contains non-link data")))?));
+                                "Link layer contains non-link data".to_string()))?));
                         }
                         Ok(PyLayer::L2(result))
                     },
@@ -203,7 +706,7 @@ impl Layer {
                         let mut result = Vec::new();
                         for (start, data) in val {
                             let tl = data.clone().into_link().ok_or_else(|| TeangaError::ModelError(
-                                format!("Typed link layer contains non-link data")))?;
+                                "Typed link layer contains non-link data".to_string()))?;
                             result.push((*start, tl.0, tl.1));
                         }
                         Ok(PyLayer::L2S(result))
@@ -213,13 +716,13 @@ impl Layer {
             Layer::Span(val) => {
                 match meta.data {
                     None => Err(TeangaError::ModelError(
-                        format!("Layer contains data but no data type"))),
+                        "Layer contains data but no data type".to_string())),
                     Some(DataType::String) => {
                         let mut result = Vec::new();
                         for (start, end, data) in val {
                             result.push((*start, *end, 
                                     data.clone().into_str().ok_or_else(|| TeangaError::ModelError(
-                                        format!("String layer contains non-string data")))?));
+                                        "String layer contains non-string data".to_string()))?));
                         }
                         Ok(PyLayer::L2S(result))
                     },
@@ -228,7 +731,7 @@ impl Layer {
                         for (start, end, data) in val {
                             result.push((*start, *end, 
                                     data.clone().into_str().ok_or_else(|| TeangaError::ModelError(
-                                        format!("String layer contains non-string data")))?));
+                                        "String layer contains non-string data".to_string()))?));
                         }
                         Ok(PyLayer::L2S(result))
                     },
@@ -237,7 +740,7 @@ impl Layer {
                         for (start, end, data) in val {
                             result.push((*start, *end, 
                                     data.clone().into_usize().ok_or_else(|| TeangaError::ModelError(
-                                        format!("Link layer contains non-link data")))?));
+                                        "Link layer contains non-link data".to_string()))?));
                         }
                         Ok(PyLayer::L3(result))
                     },
@@ -245,7 +748,7 @@ impl Layer {
                         let mut result = Vec::new();
                         for (start, end, data) in val {
                             let tl = data.clone().into_link().ok_or_else(|| TeangaError::ModelError(
-                                format!("Typed link layer contains non-link data")))?;
+                                "Typed link layer contains non-link data".to_string()))?;
                             result.push((*start, *end, tl.0, tl.1));
                         }
                         Ok(PyLayer::L3S(result))
@@ -276,114 +779,92 @@ impl Layer {
         }
     }
 
+    /// Convert a layer from its serialized form. The form must be the one
+    /// given by `PyForm::for_layer`, so that this is the inverse of `into_py`
     fn from_py(obj : PyLayer, meta : &LayerDesc) -> TeangaResult<Layer> {
-        match obj {
-            PyLayer::CharacterLayer(val) => Ok(Layer::Characters(val)),
-            PyLayer::L1(val) => {
-                match meta.data {
-                    Some(_) => {
-                        Ok(Layer::Seq(val.into_iter().map(|x| Data::from_usize(x)).collect()))
-                    },
-                    None => {
-                        match meta.layer_type {
-                            LayerType::Div => Ok(Layer::DivNoData(
-                                    val.into_iter().map(|x| x as usize).collect())),
-                            LayerType::Element => Ok(Layer::ElementNoData(
-                                    val.into_iter().map(|x| x as usize).collect())),
-                            _ => Err(TeangaError::ModelError(
-                                format!("Cannot convert data layer to {}", meta.layer_type)))
-                        }
-                    }
-                }
-            },
-            PyLayer::L2(val) => {   
-                match meta.data {
-                    Some(_) => {
-                        match meta.layer_type {
-                            LayerType::Div => Ok(Layer::Div(
-                                    val.into_iter().map(|(start, end)| 
-                                        (start, Data::from_usize(end))).collect())),
-                            LayerType::Element => Ok(Layer::Element(
-                                    val.into_iter().map(|(start, end)| 
-                                        (start, Data::from_usize(end))).collect())),
-                            _ => Err(TeangaError::ModelError(
-                                format!("Cannot convert data layer to {}", meta.layer_type)))
-                        }
-                    },
-                    None => {
-                        Ok(Layer::SpanNoData(
-                                val.into_iter().map(|(start, end)| 
-                                    (start as usize, end as usize)).collect()))
-                    }
-                }
-            },
-            PyLayer::L3(val) => {
-                Ok(Layer::Span(
-                        val.into_iter().map(|(start, end, idx)| 
-                            (start as usize, end as usize, Data::from_usize(idx))).collect()))
-            },
-            PyLayer::LS(val) => {
-                let mut result = Vec::new();
-                for data in val {
-                    result.push(Data::from_str(data));
-                }
-                Ok(Layer::Seq(result))
-            },
-            PyLayer::L1S(val) => {
-                match meta.data {
-                    Some(DataType::TypedLink(_)) => {
-                        let mut result = Vec::new();
-                        for (idx, link) in val {
-                            result.push(Data::from_link(idx, link));
-                        }
-                        Ok(Layer::Seq(result))
-                    },
-                    Some(_) => {
-                        let mut result = Vec::new();
-                        for (start, data) in val {
-                            result.push((start, Data::from_str(data)));
-                        }
-                        match meta.layer_type {
-                            LayerType::Div => Ok(Layer::Div(result)),
-                            LayerType::Element => Ok(Layer::Element(result)),
-                            _ => Err(TeangaError::ModelError(
-                                format!("Cannot convert data layer to {}", meta.layer_type)))
-                        }
-                    },
-                    None => Err(TeangaError::ModelError(
-                        format!("String in data, but data type is none")))
-                }
+        let expected = PyForm::for_layer(meta)?;
+        if obj.form() != expected {
+            return Err(TeangaError::LayerForm(expected.to_string(), obj.form().to_string()));
+        }
+        match (obj, &meta.layer_type) {
+            (PyLayer::CharacterLayer(val), _) => Ok(Layer::Characters(val)),
+            (PyLayer::LS(val), _) => Ok(Layer::Seq(val.into_iter().map(Data::from_str).collect())),
+            (PyLayer::L1(val), LayerType::Seq) => Ok(Layer::Seq(val.into_iter().map(Data::from_usize).collect())),
+            (PyLayer::L1S(val), LayerType::Seq) => Ok(Layer::Seq(
+                    val.into_iter().map(|(idx, link)| Data::from_link(idx, link)).collect())),
+            (PyLayer::L1(val), LayerType::Div) => Ok(Layer::DivNoData(val)),
+            (PyLayer::L1(val), _) => Ok(Layer::ElementNoData(val)),
+            (PyLayer::L1S(val), layer_type) => {
+                let result = val.into_iter().map(|(start, data)| (start, Data::from_str(data))).collect();
+                Ok(if *layer_type == LayerType::Div { Layer::Div(result) } else { Layer::Element(result) })
             },
-            PyLayer::L2S(val) => {
-                match meta.data {
-                    Some(DataType::TypedLink(_)) => {
-                        let mut result = Vec::new();
-                        for (start, idx, link) in val {
-                            result.push((start, Data::from_link(idx, link)));
-                        }
-                        match meta.layer_type {
-                            LayerType::Div => Ok(Layer::Div(result)),
-                            LayerType::Element => Ok(Layer::Element(result)),
-                            _ => Err(TeangaError::ModelError(
-                                format!("Cannot convert data layer to {}", meta.layer_type)))
-                        }
-                    },
-                    _ => {
-                        let mut result = Vec::new();
-                        for (start, end, data) in val {
-                            result.push((start, end, Data::from_str(data)));
-                        }
-                        Ok(Layer::Span(result))
-                    }
-                }
+            (PyLayer::L2(val), LayerType::Span) => Ok(Layer::SpanNoData(val)),
+            (PyLayer::L2(val), layer_type) => {
+                let result = val.into_iter().map(|(start, idx)| (start, Data::from_usize(idx))).collect();
+                Ok(if *layer_type == LayerType::Div { Layer::Div(result) } else { Layer::Element(result) })
             },
-            PyLayer::L3S(val) => {
-                let mut result = Vec::new();
-                for (start, end, idx, link) in val {
-                    result.push((start, end, Data::from_link(idx, link)));
-                }
-                Ok(Layer::Span(result))
+            (PyLayer::L2S(val), LayerType::Span) => Ok(Layer::Span(
+                    val.into_iter().map(|(start, end, data)| (start, end, Data::from_str(data))).collect())),
+            (PyLayer::L2S(val), layer_type) => {
+                let result = val.into_iter().map(|(start, idx, link)| (start, Data::from_link(idx, link))).collect();
+                Ok(if *layer_type == LayerType::Div { Layer::Div(result) } else { Layer::Element(result) })
             },
+            (PyLayer::L3(val), _) => Ok(Layer::Span(
+                    val.into_iter().map(|(start, end, idx)| (start, end, Data::from_usize(idx))).collect())),
+            (PyLayer::L3S(val), _) => Ok(Layer::Span(
+                    val.into_iter().map(|(start, end, idx, link)| (start, end, Data::from_link(idx, link))).collect())),
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq,Serialize,Deserialize)]
+/// A layer description as it is written in `_meta`
+pub(crate) struct PyLayerDesc {
+    #[serde(rename = "type")]
+    layer_type: LayerType,
+    #[serde(default = "String::new")]
+    #[serde(skip_serializing_if = "String::is_empty")]
+    on: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<DataType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    values: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    default: Option<serde_json::Value>,
+}
+
+impl From<PyLayerDesc> for LayerDesc {
+    fn from(desc : PyLayerDesc) -> LayerDesc {
+        let (data, values) = match (desc.data, desc.values) {
+            (Some(DataType::Link), Some(types)) => (Some(DataType::TypedLink(types)), None),
+            (data, values) => (data, values)
+        };
+        LayerDesc {
+            layer_type: desc.layer_type,
+            on: desc.on,
+            data,
+            values,
+            target: desc.target,
+            default: desc.default,
+        }
+    }
+}
+
+impl From<LayerDesc> for PyLayerDesc {
+    fn from(desc : LayerDesc) -> PyLayerDesc {
+        let (data, values) = match (desc.data, desc.values) {
+            (Some(DataType::TypedLink(types)), None) => (Some(DataType::Link), Some(types)),
+            (data, values) => (data, values)
+        };
+        PyLayerDesc {
+            layer_type: desc.layer_type,
+            on: desc.on,
+            data,
+            values,
+            target: desc.target,
+            default: desc.default,
         }
     }
 }
@@ -453,9 +934,252 @@ pub enum TeangaError {
     ModelError(String),
     #[error("Json error: {0}")]
     Json(#[from] serde_json::Error),
+    #[error("Yaml error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("CoNLL-U error on line {0}: {1}")]
+    ConlluError(usize, String),
+    #[error("Cannot write document {0} as CoNLL-U: {1}")]
+    ConlluExportError(String, String),
+    #[error("brat error in document {0}: {1}")]
+    BratError(String, String),
+    #[error("Zip error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("Layer {0} is not in the document")]
+    MissingLayer(String),
+    #[error("No metadata for layer {0}")]
+    MissingMeta(String),
+    #[error("Index {1} of layer {0} is out of range")]
+    IndexOutOfRange(String, usize),
+    #[error("Layer {0} is (indirectly) on itself")]
+    CyclicLayer(String),
+    #[error("Expected {0} but found {1}")]
+    LayerForm(String, String),
     #[error("UTF8 error: {0}")]
     Utf8(#[from] std::string::FromUtf8Error),
 }
 
-type TeangaResult<T> = Result<T, TeangaError>;
+pub type TeangaResult<T> = Result<T, TeangaError>;
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CORPUS_JSON : &str = "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\"},
+\"pos\":{\"type\":\"seq\",\"on\":\"tokens\",\"data\":[\"DT\",\"VBZ\",\"NN\"]}},\"_order\":[\"Kjco\"],
+\"Kjco\":{\"text\":\"This is a document.\",\"tokens\":[[0,4],[5,7],[8,9],[10,19]],\"pos\":[\"DT\",\"VBZ\",\"DT\",\"NN\"]}}";
+
+    const CORPUS_YAML : &str = "_meta:
+    text:
+        type: characters
+    tokens:
+        type: span
+        on: text
+    pos:
+        type: seq
+        on: tokens
+        data: [DT, VBZ, NN]
+_order: [Kjco]
+Kjco:
+    text: This is a document.
+    tokens: [[0, 4], [5, 7], [8, 9], [10, 19]]
+    pos: [DT, VBZ, DT, NN]
+";
+
+    #[test]
+    fn test_read_yaml() {
+        let yaml = read_corpus_from_yaml_string(CORPUS_YAML).unwrap();
+        let json = read_corpus_from_json_string(CORPUS_JSON).unwrap();
+        assert_eq!(yaml.meta, json.meta);
+        assert_eq!(yaml.order, vec!["Kjco".to_string()]);
+        assert_eq!(yaml.documents, json.documents);
+    }
+
+    #[test]
+    fn test_yaml_round_trip() {
+        let corpus = read_corpus_from_json_string(CORPUS_JSON).unwrap();
+        let yaml = write_corpus_to_yaml_string(&corpus).unwrap();
+        let corpus2 = read_corpus_from_yaml_string(&yaml).unwrap();
+        assert_eq!(corpus.meta, corpus2.meta);
+        assert_eq!(corpus.order, corpus2.order);
+        assert_eq!(corpus.documents, corpus2.documents);
+    }
+
+    #[test]
+    fn test_write_order() {
+        let corpus = read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"}},\"_order\":[\"gone\",\"abcd\"],
+\"Kjco\":{\"text\":\"This is a document.\"},
+\"abcd\":{\"text\":\"This is a second document\"}}").unwrap();
+        let corpus2 = read_corpus_from_json_string(&write_corpus_to_json_string(&corpus).unwrap()).unwrap();
+        assert_eq!(corpus2.order, vec!["abcd".to_string(), "Kjco".to_string()]);
+        assert_eq!(corpus2.documents[0].0, "abcd");
+    }
+
+    #[test]
+    fn test_read_lenient() {
+        let json = "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\"}},
+\"_order\":[\"Kjco\",\"abcd\",\"efgh\"],
+\"Kjco\":{\"text\":\"This is a document.\",\"tokens\":[[0,4],[5,7],[8,9],[10,19]]},
+\"abcd\":{\"text\":\"This is a second document\",\"pos\":[\"DT\"]},
+\"efgh\":{\"text\":\"Third\",\"tokens\":5}}";
+        assert!(read_corpus_from_json_string(json).is_err());
+        let (corpus, quarantined) = read_corpus_from_json_string_lenient(json).unwrap();
+        assert_eq!(corpus.documents.len(), 1);
+        assert_eq!(corpus.documents[0].0, "Kjco");
+        assert_eq!(quarantined.len(), 2);
+        assert_eq!(quarantined[0].id, "abcd");
+        assert_eq!(quarantined[0].error, "No metadata for layer pos");
+        assert_eq!(quarantined[1].id, "efgh");
+        assert_eq!(corpus.order, vec!["Kjco".to_string()]);
+        assert_eq!(corpus.check_ids(), Vec::new());
+        let saved = write_quarantined_to_json_string(&corpus.meta, &quarantined).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&saved).unwrap()["_order"], serde_json::json!(["abcd", "efgh"]));
+        let saved = read_corpus_from_json_string_lenient(&saved).unwrap();
+        assert!(saved.0.order.is_empty());
+        assert_eq!(saved.1.len(), 2);
+    }
+
+    #[test]
+    fn test_layer_round_trip() {
+        let data_types = [None, Some(DataType::String), Some(DataType::Enum(vec!["a".to_string()])),
+            Some(DataType::Link), Some(DataType::TypedLink(vec!["a".to_string()]))];
+        for layer_type in [LayerType::Seq, LayerType::Div, LayerType::Element, LayerType::Span] {
+            for data_type in data_types.iter() {
+                let data = |i : usize| match data_type {
+                    Some(DataType::Link) => Data::Link(i),
+                    Some(DataType::TypedLink(_)) => Data::TypedLink(i, "a".to_string()),
+                    _ => Data::String("a".to_string())
+                };
+                let layer = match (&layer_type, data_type.is_some()) {
+                    (LayerType::Seq, false) => continue,
+                    (LayerType::Seq, true) => Layer::Seq(vec![data(1), data(0)]),
+                    (LayerType::Div, false) => Layer::DivNoData(vec![0, 1]),
+                    (LayerType::Div, true) => Layer::Div(vec![(0, data(1)), (1, data(0))]),
+                    (LayerType::Element, false) => Layer::ElementNoData(vec![0, 1]),
+                    (LayerType::Element, true) => Layer::Element(vec![(0, data(1)), (1, data(0))]),
+                    (LayerType::Span, false) => Layer::SpanNoData(vec![(0, 1), (1, 2)]),
+                    (LayerType::Span, true) => Layer::Span(vec![(0, 1, data(1)), (1, 2, data(0))]),
+                    _ => unreachable!()
+                };
+                let meta = LayerDesc { layer_type: layer_type.clone(), on: "tokens".to_string(),
+                    data: data_type.clone(), values: None, target: None, default: None };
+                let py = layer.into_py(&meta).unwrap();
+                assert_eq!(Layer::from_py(py.clone(), &meta).unwrap(), layer);
+                let json = serde_json::to_string(&py).unwrap();
+                let mut deserializer = serde_json::Deserializer::from_str(&json);
+                let py2 = PyForm::for_layer(&meta).unwrap().deserialize(&mut deserializer).unwrap();
+                assert_eq!(py2, py, "{} {:?}", layer_type, data_type);
+            }
+        }
+    }
+
+    #[test]
+    fn test_meta_round_trip() {
+        let data_types = [None, Some(DataType::String), Some(DataType::Enum(vec!["a".to_string(), "b".to_string()])),
+            Some(DataType::Link), Some(DataType::TypedLink(vec!["nsubj".to_string(), "obj".to_string()]))];
+        let mut corpus = Corpus::new();
+        corpus.meta.insert("text".to_string(), LayerDesc { layer_type: LayerType::Characters,
+            on: String::new(), data: None, values: None, target: None, default: None });
+        for layer_type in [LayerType::Seq, LayerType::Div, LayerType::Element, LayerType::Span] {
+            for (i, data_type) in data_types.iter().enumerate() {
+                corpus.meta.insert(format!("{}{}", layer_type, i), LayerDesc {
+                    layer_type: layer_type.clone(),
+                    on: "text".to_string(),
+                    data: data_type.clone(),
+                    values: None,
+                    target: if i >= 3 { Some("span0".to_string()) } else { None },
+                    default: if i == 1 { Some(serde_json::json!(["x"])) } else { None },
+                });
+            }
+        }
+        let json = write_corpus_to_json_string(&corpus).unwrap();
+        assert_eq!(read_corpus_from_json_string(&json).unwrap().meta, corpus.meta);
+        let yaml = write_corpus_to_yaml_string(&corpus).unwrap();
+        assert_eq!(read_corpus_from_yaml_string(&yaml).unwrap().meta, corpus.meta);
+        let value : serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["_meta"]["span4"], serde_json::json!({"type": "span", "on": "text",
+            "data": "link", "values": ["nsubj", "obj"], "target": "span0"}));
+    }
+
+    #[test]
+    fn test_meta_values_kept() {
+        let json = "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\",\"data\":\"string\",\"values\":[\"a\"]}},\"_order\":[]}";
+        let corpus = read_corpus_from_json_string(json).unwrap();
+        assert_eq!(corpus.meta["tokens"].data, Some(DataType::String));
+        let value : serde_json::Value = serde_json::from_str(&write_corpus_to_json_string(&corpus).unwrap()).unwrap();
+        assert_eq!(value, serde_json::from_str::<serde_json::Value>(json).unwrap());
+    }
+
+    #[test]
+    fn test_read_layer_by_type() {
+        let corpus = read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\"},
+\"pos\":{\"type\":\"seq\",\"on\":\"tokens\",\"data\":\"string\"}},
+\"Kjco\":{\"text\":\"This is a document.\",\"tokens\":[],\"pos\":[]}}").unwrap();
+        assert_eq!(corpus.documents[0].1.content["tokens"], Layer::SpanNoData(Vec::new()));
+        assert_eq!(corpus.documents[0].1.content["pos"], Layer::Seq(Vec::new()));
+        let error = read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\"}},
+\"Kjco\":{\"text\":\"This is a document.\",\"tokens\":[[0,4],[5,\"x\"]]}}").unwrap_err().to_string();
+        assert!(error.starts_with("Layer tokens: item 1 should be a pair of indices: invalid type: string \"x\", expected usize"), "{}", error);
+        let error = read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\"}},
+\"Kjco\":{\"text\":\"This is a document.\",\"tokens\":\"x\"}}").unwrap_err().to_string();
+        assert!(error.starts_with("Layer tokens: invalid type: string \"x\", expected a list of pairs of indices"), "{}", error);
+    }
+
+    #[test]
+    fn test_write_deterministic() {
+        let corpus = read_corpus_from_json_string(CORPUS_JSON).unwrap();
+        let json = write_corpus_to_json_string(&corpus).unwrap();
+        assert_eq!(json, "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\"},\
+\"pos\":{\"type\":\"seq\",\"on\":\"tokens\",\"data\":[\"DT\",\"VBZ\",\"NN\"]}},\"_order\":[\"Kjco\"],\
+\"Kjco\":{\"text\":\"This is a document.\",\"tokens\":[[0,4],[5,7],[8,9],[10,19]],\"pos\":[\"DT\",\"VBZ\",\"DT\",\"NN\"]}}");
+        let pretty = write_corpus_to_json_string_pretty(&corpus).unwrap();
+        assert_eq!(pretty, "{
+  \"_meta\": {
+    \"text\": {\"type\":\"characters\"},
+    \"tokens\": {\"type\":\"span\",\"on\":\"text\"},
+    \"pos\": {\"type\":\"seq\",\"on\":\"tokens\",\"data\":[\"DT\",\"VBZ\",\"NN\"]}
+  },
+  \"_order\": [
+    \"Kjco\"
+  ],
+  \"Kjco\": {
+    \"text\": \"This is a document.\",
+    \"tokens\": [[0,4],[5,7],[8,9],[10,19]],
+    \"pos\": [\"DT\",\"VBZ\",\"DT\",\"NN\"]
+  }
+}");
+        assert_eq!(read_corpus_from_json_string(&pretty).unwrap().documents, corpus.documents);
+        assert_eq!(write_corpus_to_yaml_string(&corpus).unwrap(), write_corpus_to_yaml_string(&corpus).unwrap());
+    }
+
+    #[test]
+    fn test_layer_defaults() {
+        let json = "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"sentences\":{\"type\":\"div\",\"on\":\"text\",\"default\":[0]}},
+\"_order\":[\"Kjco\",\"abcd\"],
+\"Kjco\":{\"text\":\"This is a document.\"},
+\"abcd\":{\"text\":\"This is a second document\",\"sentences\":[0,5]}}";
+        let corpus = read_corpus_from_json_string(json).unwrap();
+        assert_eq!(corpus.documents[0].1.content["sentences"], Layer::DivNoData(vec![0]));
+        assert!(corpus.documents[0].1.defaults.contains("sentences"));
+        assert!(corpus.documents[1].1.defaults.is_empty());
+        let value : serde_json::Value = serde_json::from_str(&write_corpus_to_json_string(&corpus).unwrap()).unwrap();
+        assert_eq!(value, serde_json::from_str::<serde_json::Value>(json).unwrap());
+        let bad = json.replace("\"default\":[0]", "\"default\":\"x\"");
+        assert!(read_corpus_from_json_string(&bad).is_err());
+    }
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(Format::from_filename("corpus.json"), Some(Format::Json));
+        assert_eq!(Format::from_filename("corpus.YML"), Some(Format::Yaml));
+        assert_eq!(Format::from_filename("corpus"), None);
+        assert_eq!(Format::from_content(CORPUS_JSON), Format::Json);
+        assert_eq!(Format::from_content(CORPUS_YAML), Format::Yaml);
+        assert_eq!(Format::from_content("Just some text: nothing more"), Format::Text);
+        assert_eq!(Format::from_content("# sent_id = 1\n1\tHi\t_\t_\t_\t_\t0\troot\t_\t_\n"), Format::Conllu);
+    }
+}
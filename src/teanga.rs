@@ -1,8 +1,14 @@
 /// The Teanga data model as implemented by this model
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use base64::Engine;
 use std::fmt::{self, Display, Formatter};
-use std::cmp::Ordering;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::rc::Rc;
+use crate::offsets::char_len;
+use crate::serialization::{PyLayerDesc, TeangaError, TeangaResult};
 
 #[derive(Debug,Clone)]
 /// A corpus object
@@ -10,6 +16,9 @@ pub struct Corpus {
     pub meta: HashMap<String, LayerDesc>,
     pub order: Vec<String>,
     pub documents: Vec<(String, Document)>,
+    /// The name of the file that each document was imported from, by
+    /// document id, used only to name the files it is exported to
+    pub filenames: HashMap<String, String>,
 }
 
 impl Corpus {
@@ -18,47 +27,157 @@ impl Corpus {
             meta: HashMap::new(),
             order: Vec::new(),
             documents: Vec::new(),
+            filenames: HashMap::new(),
+        }
+    }
+
+    /// Create a corpus from documents, naming each by its Teanga identifier
+    pub fn from_documents(meta : HashMap<String, LayerDesc>, docs : Vec<Document>) -> Self {
+        let mut corpus = Corpus::new();
+        corpus.meta = meta;
+        let mut ids = HashSet::new();
+        for doc in docs {
+            let id = teanga_id(&ids, &doc);
+            ids.insert(id.clone());
+            corpus.order.push(id.clone());
+            corpus.documents.push((id, doc));
+        }
+        corpus
+    }
+
+    /// The indices of the documents in the order they should be shown: first
+    /// those listed in `_order`, then any others in the order they were read
+    pub fn doc_order(&self) -> Vec<usize> {
+        let index : HashMap<&str, usize> = self.documents.iter().enumerate().rev()
+            .map(|(i, (id, _))| (id.as_str(), i)).collect();
+        let mut seen = vec![false; self.documents.len()];
+        let mut order = Vec::new();
+        for id in self.order.iter() {
+            if let Some(&i) = index.get(id.as_str()) {
+                if !seen[i] {
+                    seen[i] = true;
+                    order.push(i);
+                }
+            }
+        }
+        order.extend((0..self.documents.len()).filter(|i| !seen[*i]));
+        order
+    }
+
+    /// Check the document identifiers against the content of the documents
+    /// and against `_order`
+    pub fn check_ids(&self) -> Vec<IdProblem> {
+        let mut problems = Vec::new();
+        let mut ids = HashSet::new();
+        for (id, doc) in self.documents.iter() {
+            if !ids.insert(id.as_str()) {
+                problems.push(IdProblem::DuplicateDocument(id.clone()));
+            }
+            let code = teanga_code(doc);
+            if id.len() < 4 || !code.starts_with(id.as_str()) {
+                let others = self.documents.iter()
+                    .filter(|(other, _)| other != id)
+                    .map(|(other, _)| other.clone()).collect();
+                problems.push(IdProblem::WrongId {
+                    id: id.clone(),
+                    expected: teanga_id(&others, doc)
+                });
+            }
+        }
+        let order : HashSet<&str> = self.order.iter().map(|id| id.as_str()).collect();
+        for id in self.order.iter() {
+            if !ids.contains(id.as_str()) {
+                problems.push(IdProblem::MissingDocument(id.clone()));
+            }
+        }
+        for (id, _) in self.documents.iter() {
+            if !order.contains(id.as_str()) {
+                problems.push(IdProblem::NotInOrder(id.clone()));
+            }
+        }
+        problems
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+/// A problem with the identifiers of the documents of a corpus
+pub enum IdProblem {
+    /// The identifier does not match the content of the document
+    WrongId { id: String, expected: String },
+    /// The identifier is used for more than one document
+    DuplicateDocument(String),
+    /// The identifier is listed in `_order` but there is no such document
+    MissingDocument(String),
+    /// The document is not listed in `_order`
+    NotInOrder(String),
+}
+
+impl Display for IdProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            IdProblem::WrongId { id, expected } =>
+                write!(f, "Document {} should have the identifier {}", id, expected),
+            IdProblem::DuplicateDocument(id) => write!(f, "Document {} occurs more than once", id),
+            IdProblem::MissingDocument(id) => write!(f, "Document {} is in _order but not in the corpus", id),
+            IdProblem::NotInOrder(id) => write!(f, "Document {} is not in _order", id),
         }
     }
 }
 
 #[derive(Debug,Clone,PartialEq,Serialize,Deserialize)]
-/// A layer description
+#[serde(from = "PyLayerDesc", into = "PyLayerDesc")]
+/// A layer description. The types of a typed link layer are written as the
+/// `values` of a `link` layer.
 pub struct LayerDesc {
-    #[serde(rename = "type")]
     pub layer_type: LayerType,
-    #[serde(default = "String::new")]
-    #[serde(skip_serializing_if = "String::is_empty")]
     pub on: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<DataType>,
-    #[serde(skip_serializing_if = "Option::is_none")]
     pub values: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// The layer that links point to, if not the layer this layer is on
     pub target: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub default: Option<Vec<String>>,
+    /// The content of the layer in documents that omit it, in the same form
+    /// as the layer is written in a document
+    pub default: Option<serde_json::Value>,
+}
+
+impl LayerDesc {
+    /// A layer description without values, a target or a default
+    pub fn new(layer_type : LayerType, on : &str, data : Option<DataType>) -> LayerDesc {
+        LayerDesc {
+            layer_type,
+            on: on.to_string(),
+            data,
+            values: None,
+            target: None,
+            default: None
+        }
+    }
 }
 
+/// The name of the characters layer that importers put the text in
+pub const TEXT_LAYER : &str = "text";
+
 #[derive(Debug)]
-pub struct DocSecs<'a,'b> {
-    pub content : &'a str,
-    pub annos : Vec<Anno<'a,'b>>
+pub struct DocSecs {
+    pub content : String,
+    pub annos : Vec<Anno>
 }
 
 #[derive(Debug, PartialEq, Clone)]
-pub struct Anno<'a,'b> {
-    pub layer_name : &'b str,
-    pub data : Option<&'a Data>,
+pub struct Anno {
+    pub layer_name : Rc<str>,
+    pub data : Option<Data>,
     pub left_complete : bool,
     pub right_complete : bool,
     pub start : usize,
     pub end : usize,
-    pub children : Vec<Anno<'a,'b>>,
+    /// The index of the annotation in its layer
+    pub index : usize,
+    pub children : Vec<Anno>,
 }
 
-impl<'a,'b> Anno<'a,'b> {
-    pub fn new(layer_name : &'b str,  data : Option<&'a Data>, start : usize, end : usize) -> Self {
+impl Anno {
+    pub fn new(layer_name : Rc<str>,  data : Option<Data>, start : usize, end : usize) -> Self {
         Anno {
             layer_name,
             data,
@@ -66,6 +185,7 @@ impl<'a,'b> Anno<'a,'b> {
             right_complete: true,
             start,
             end,
+            index: 0,
             children: Vec::new()
         }
     }
@@ -74,276 +194,318 @@ impl<'a,'b> Anno<'a,'b> {
 #[derive(Debug,Clone, PartialEq)]
 /// A document object
 pub struct Document {
-    pub content: HashMap<String, Layer>
+    pub content: HashMap<String, Layer>,
+    /// The layers that the document omits and that were filled in from the
+    /// `default` of their description. These are not written when saving,
+    /// so a layer should be removed from here if its content is changed.
+    pub defaults: HashSet<String>,
 }
 
 impl Document {
     pub fn new() -> Self {
         Document {
-            content: HashMap::new()
+            content: HashMap::new(),
+            defaults: HashSet::new(),
         }
     }
 
     pub fn get_text_layers(&self) -> HashMap<String, &String> {
         let mut text_layers = HashMap::new();
         for (layer_name, layer) in self.content.iter() {
-            match layer {
-                Layer::Characters(s) => {
-                    text_layers.insert(layer_name.clone(), s);
-                },
-                _ => ()
+            if let Layer::Characters(s) = layer {
+                text_layers.insert(layer_name.clone(), s);
             }
         }
         text_layers
     }
 
-    pub fn get_annos<'a,'b>(&'a self, meta : &'b HashMap<String, LayerDesc>) -> Result<HashMap<String, DocSecs<'a,'b>>, String> where 'a: 'b {
+    /// Build the annotations of each character layer. Layers whose
+    /// annotations cannot be built are left out and their errors returned
+    /// by layer name alongside the sections.
+    pub fn get_annos(&self, meta : &HashMap<String, LayerDesc>) -> (HashMap<String, DocSecs>, HashMap<String, TeangaError>) {
         let mut annos = HashMap::new();
         let mut base_annos = HashMap::new();
+        let mut errors = HashMap::new();
         let layer_tree = LayerTree::from_meta(meta);
         for (layer_name, layer) in self.content.iter() {
-            match layer {
-                Layer::Characters(s) => {
-                    annos.insert(layer_name.clone(), DocSecs {
-                        content : &s,
-                        annos : Vec::new()
-                    });
-                },
-                _ => {
-                    let (base, on) = self.base_annos(layer_name, meta)?;
-                    base_annos.entry(on).or_insert_with(Vec::new).extend(base);
-                }
+            if let Layer::Characters(s) = layer {
+                annos.insert(layer_name.clone(), DocSecs {
+                    content : s.clone(),
+                    annos : Vec::new()
+                });
             }
         }
-        for (base_layer_name, doc_secs) in annos.iter_mut() {
-            let base_annos = base_annos.entry(base_layer_name).or_insert_with(Vec::new);
-            let mut annos2 = Vec::new();
-            let divisions = calc_divisions(&base_annos);
-            'anno_loop: for anno in base_annos.iter() {
-                for (i,j) in divisions.iter() {
-                    let i = *i;
-                    let j = *j;
-                    if anno.start >= i && anno.end <= j {
-                        let anno2 = if anno.start == i && anno.end == j {
-                            Anno::new(anno.layer_name, anno.data, i, j)
-                        } else if anno.start == i {
-                            let mut anno = Anno::new(anno.layer_name, anno.data, i, j);
-                            anno.left_complete = false;
-                            anno
-                        } else if anno.end == j {
-                            let mut anno = Anno::new(anno.layer_name, anno.data, i, j);
-                            anno.right_complete = false;
-                            anno
-                        } else {
-                            let mut anno = Anno::new(anno.layer_name, anno.data, i, j);
-                            anno.left_complete = false;
-                            anno.right_complete = false;
-                            anno
-                        };
-                        annos2.push(anno2);
-                        continue 'anno_loop;
-                     }
-                }
-           }
-           
-            annos2.sort_by(|a,b| {
-                if a.start < b.start {
-                    std::cmp::Ordering::Less
-                } else if a.start > b.start {
-                    std::cmp::Ordering::Greater
-                } else if a.end < b.end {
-                    std::cmp::Ordering::Greater
-                } else if a.end > b.end {
-                    std::cmp::Ordering::Less
-                } else {
-                    layer_tree.cmp_str(a.layer_name, b.layer_name)
+        let mut layer_names : Vec<&String> = self.content.keys().collect();
+        layer_names.sort();
+        for layer_name in layer_names {
+            if let Layer::Characters(_) = self.content[layer_name] {
+                continue;
+            }
+            let result = self.base_annos(layer_name, meta).and_then(|(base, on)| {
+                let len = annos.get(on).map_or(0, |secs : &DocSecs| char_len(&secs.content));
+                match base.iter().position(|a| a.start > a.end || a.end > len) {
+                    Some(i) => Err(TeangaError::IndexOutOfRange(layer_name.clone(), i)),
+                    None => Ok((base, on))
                 }
             });
-           doc_secs.annos = merge_annos_recursively(annos2);
+            match result {
+                Ok((base, on)) => base_annos.entry(on).or_insert_with(Vec::new).extend(base),
+                Err(e) => { errors.insert(layer_name.clone(), e); }
+            }
+        }
+        let ranks = layer_tree.ranks();
+        for (base_layer_name, doc_secs) in annos.iter_mut() {
+            let base_annos = base_annos.remove(base_layer_name.as_str()).unwrap_or_default();
+            doc_secs.annos = layout_annos(base_annos, &ranks);
         }
 
-        Ok(annos)
+        (annos, errors)
     }
 
-    fn base_annos<'a,'b>(&'a self, name : &'b str, meta : &'b HashMap<String, LayerDesc>) -> Result<(Vec<Anno<'a,'b>>, &'b str),String> {
-        let layer = self.content.get(name).ok_or_else(|| format!("No layer {}", name))?;
-        let this_meta = meta.get(name).ok_or_else(|| format!("No meta data for layer {}", name))?;
-        match layer {
-            Layer::Characters(_) => Err("Base index cannot be called on a character layer".to_string()),
+    /// The annotations of a layer as offsets into the character layer it is
+    /// ultimately based on, with the name of that layer
+    pub(crate) fn base_annos<'b>(&self, name : &'b str, meta : &'b HashMap<String, LayerDesc>) -> TeangaResult<(Vec<Anno>, &'b str)> {
+        let layer = self.content.get(name).ok_or_else(|| TeangaError::MissingLayer(name.to_string()))?;
+        let layer_name : Rc<str> = Rc::from(name);
+        let this_meta = meta.get(name).ok_or_else(|| TeangaError::MissingMeta(name.to_string()))?;
+        check_acyclic(name, meta)?;
+        let (mut base, on) = match layer {
+            Layer::Characters(_) => Err(TeangaError::ModelError("Base index cannot be called on a character layer".to_string())),
             Layer::Seq(data) => {
-                match self.content.get(&this_meta.on).ok_or_else(|| format!("No data for layer {}", name))? {
+                match self.content.get(&this_meta.on).ok_or_else(|| TeangaError::MissingLayer(this_meta.on.clone()))? {
                     Layer::Characters(s) =>
-                        Ok(((0..s.len()).map(|i|
-                                Anno::new(name, None, i,i+1)).collect(), &this_meta.on)),
+                        Ok(((0..char_len(s)).zip(data.iter()).map(|(i, d)|
+                                Anno::new(layer_name.clone(), Some(d.clone()), i,i+1)).collect(), this_meta.on.as_str())),
                     _ => {
                         let mut base = Vec::new();
                         let (annos, on) = self.base_annos(&this_meta.on, meta)?;
                         for (a, d) in annos.iter().zip(data.iter()) {
-                            base.push(Anno::new(name, Some(d), a.start, a.end));
+                            base.push(Anno::new(layer_name.clone(), Some(d.clone()), a.start, a.end));
                         }
                         Ok((base, on))
                     }
                 }
             },
             Layer::Div(data) => {
-                match self.content.get(&this_meta.on).ok_or_else(|| format!("No data for layer {}", name))? {
+                match self.content.get(&this_meta.on).ok_or_else(|| TeangaError::MissingLayer(this_meta.on.clone()))? {
                     Layer::Characters(s) => {
                         let mut base = Vec::new();
                         let mut start : Option<usize> = None;
-                        let mut last_d = None;
+                        let mut last_d : Option<&Data> = None;
                         for (i,d) in data.iter() {
-                            match start {
-                                Some(start) => base.push(Anno::new(name, last_d, start, *i)),
-                                None => {}
+                            if let Some(start) = start {
+                                base.push(Anno::new(layer_name.clone(), last_d.cloned(), start, *i));
                             }
                             start = Some(*i);
                             last_d = Some(d);
                         }
-                        match start {
-                            Some(start) => base.push(Anno::new(name, last_d, start, s.len())),
-                            None => {}
+                        if let Some(start) = start {
+                            base.push(Anno::new(layer_name.clone(), last_d.cloned(), start, char_len(s)));
                         }
-                        Ok((base, &this_meta.on))
+                        Ok((base, this_meta.on.as_str()))
                     },
                     _ => { 
                         let (indexes, on) = self.base_annos(&this_meta.on, meta)?;
                         let mut base = Vec::new();
                         let mut start = None;
-                        let mut last_d = None;
+                        let mut last_d : Option<&Data> = None;
                         for (i,d) in data.iter() {
-                            match start {
-                                Some(start) => base.push(Anno::new(name, last_d, start, indexes[*i].end)),
-                                None => {}
+                            if let Some(start) = start {
+                                base.push(Anno::new(layer_name.clone(), last_d.cloned(), start, base_index(&indexes, name, *i)?.end));
                             }
-                            start = Some(indexes[*i].start);
+                            start = Some(base_index(&indexes, name, *i)?.start);
                             last_d = Some(d);
                         }
-                        match start {
-                            Some(start) => base.push(Anno::new(name, 
-                                    last_d, start, indexes[indexes.len()-1].end)),
-                            None => {}
+                        if let Some(start) = start {
+                            base.push(Anno::new(layer_name.clone(), last_d.cloned(), start, indexes.last().map_or(start, |a| a.end)));
                         }
                         Ok((base,on))
                     }
                 }
             },
             Layer::DivNoData(data) => {
-                match self.content.get(&this_meta.on).ok_or_else(|| format!("No data for layer {}", name))? {
+                match self.content.get(&this_meta.on).ok_or_else(|| TeangaError::MissingLayer(this_meta.on.clone()))? {
                     Layer::Characters(s) => {
                         let mut base = Vec::new();
                         let mut start : Option<usize> = None;
                         for i in data.iter() {
-                            match start {
-                                Some(start) => base.push(Anno::new(name, None, start, *i)),
-                                None => {}
+                            if let Some(start) = start {
+                                base.push(Anno::new(layer_name.clone(), None, start, *i));
                             }
                             start = Some(*i);
                         }
-                        match start {
-                            Some(start) => base.push(Anno::new(name, None, start, s.len())),
-                            None => {}
+                        if let Some(start) = start {
+                            base.push(Anno::new(layer_name.clone(), None, start, char_len(s)));
                         }
-                        Ok((base, &this_meta.on))
+                        Ok((base, this_meta.on.as_str()))
                     },
                     _ => { 
                         let (indexes, on) = self.base_annos(&this_meta.on, meta)?;
                         let mut base = Vec::new();
                         let mut start = None;
                         for i in data.iter() {
-                            match start {
-                                Some(start) => base.push(Anno::new(name, None, start, indexes[*i].end)),
-                                None => {}
+                            if let Some(start) = start {
+                                base.push(Anno::new(layer_name.clone(), None, start, base_index(&indexes, name, *i)?.end));
                             }
-                            start = Some(indexes[*i].start);
+                            start = Some(base_index(&indexes, name, *i)?.start);
                         }
-                        match start {
-                            Some(start) => base.push(Anno::new(name, None, start, indexes[indexes.len()-1].end)),
-                            None => {}
+                        if let Some(start) = start {
+                            base.push(Anno::new(layer_name.clone(), None, start, indexes.last().map_or(start, |a| a.end)));
                         }
                         Ok((base,on))
                     }
                 }
              },
              Layer::Element(data) => {
-                match self.content.get(&this_meta.on).ok_or_else(|| format!("No data for layer {}", name))? {
+                match self.content.get(&this_meta.on).ok_or_else(|| TeangaError::MissingLayer(this_meta.on.clone()))? {
                     Layer::Characters(_) => {
                         let mut base = Vec::new();
                         for (i,d) in data.iter() {
-                            base.push(Anno::new(name, Some(d), *i, i+1));
+                            base.push(Anno::new(layer_name.clone(), Some(d.clone()), *i, i+1));
                         }
-                        Ok((base, &this_meta.on))
+                        Ok((base, this_meta.on.as_str()))
                     },
                     _ => {
                         let (indexes, on) = self.base_annos(&this_meta.on, meta)?;
                         let mut base = Vec::new();
                         for (i,d) in data.iter() {
-                            base.push(Anno::new(name, Some(d), indexes[*i].start, indexes[*i].end));
+                            base.push(Anno::new(layer_name.clone(), Some(d.clone()), base_index(&indexes, name, *i)?.start, base_index(&indexes, name, *i)?.end));
                         }
                         Ok((base,on))
                     }
                 }
              },
              Layer::ElementNoData(data) => {
-                match self.content.get(&this_meta.on).ok_or_else(|| format!("No data for layer {}", name))? {
+                match self.content.get(&this_meta.on).ok_or_else(|| TeangaError::MissingLayer(this_meta.on.clone()))? {
                     Layer::Characters(_) => {
                         let mut base = Vec::new();
                         for i in data.iter() {
-                            base.push(Anno::new(name, None, *i, i+1));
+                            base.push(Anno::new(layer_name.clone(), None, *i, i+1));
                         }
-                        Ok((base, &this_meta.on))
+                        Ok((base, this_meta.on.as_str()))
                     },
                     _ => {
                         let (indexes, on) = self.base_annos(&this_meta.on, meta)?;
                         let mut base = Vec::new();
                         for i in data.iter() {
-                            base.push(Anno::new(name, None, indexes[*i].start, indexes[*i].end));
+                            base.push(Anno::new(layer_name.clone(), None, base_index(&indexes, name, *i)?.start, base_index(&indexes, name, *i)?.end));
                         }
                         Ok((base,on))
                     }
                 }
              },
              Layer::Span(data) => {
-                match self.content.get(&this_meta.on).ok_or_else(|| format!("No data for layer {}", name))? {
+                match self.content.get(&this_meta.on).ok_or_else(|| TeangaError::MissingLayer(this_meta.on.clone()))? {
                     Layer::Characters(_) => {
                         let mut base = Vec::new();
                         for (i,j,d) in data.iter() {
-                            base.push(Anno::new(name, Some(d), *i, *j));
+                            base.push(Anno::new(layer_name.clone(), Some(d.clone()), *i, *j));
                         }
-                        Ok((base, &this_meta.on))
+                        Ok((base, this_meta.on.as_str()))
                     },
                     _ => {
                         let (indexes, on) = self.base_annos(&this_meta.on, meta)?;
                         let mut base = Vec::new();
                         for (i,j,d) in data.iter() {
-                            base.push(Anno::new(name, Some(d), indexes[*i].start, indexes[*j-1].end));
+                            base.push(Anno::new(layer_name.clone(), Some(d.clone()), base_index(&indexes, name, *i)?.start, base_index(&indexes, name, j.saturating_sub(1))?.end));
                         }
                         Ok((base,on))
                     }
                 }
              },
              Layer::SpanNoData(data) => {
-                match self.content.get(&this_meta.on).ok_or_else(|| format!("No data for layer {}", name))? {
+                match self.content.get(&this_meta.on).ok_or_else(|| TeangaError::MissingLayer(this_meta.on.clone()))? {
                     Layer::Characters(_) => {
                         let mut base = Vec::new();
                         for (i,j) in data.iter() {
-                            base.push(Anno::new(name, None, *i, *j));
+                            base.push(Anno::new(layer_name.clone(), None, *i, *j));
                         }
-                        Ok((base, &this_meta.on))
+                        Ok((base, this_meta.on.as_str()))
                     },
                     _ => {
                         let (indexes, on) = self.base_annos(&this_meta.on, meta)?;
                         let mut base = Vec::new();
                         for (i,j) in data.iter() {
-                            base.push(Anno::new(name, None, indexes[*i].start, indexes[*j-1].end));
+                            base.push(Anno::new(layer_name.clone(), None, base_index(&indexes, name, *i)?.start, base_index(&indexes, name, j.saturating_sub(1))?.end));
                         }
                         Ok((base,on))
                     }
                 }
              }
+        }?;
+        for (k, anno) in base.iter_mut().enumerate() {
+            anno.index = k;
         }
+        Ok((base, on))
     }
 }
 
+/// The annotation at an index of a base layer, or an error if the index is
+/// out of range
+fn base_index<'c>(indexes : &'c [Anno], name : &str, i : usize) -> TeangaResult<&'c Anno> {
+    indexes.get(i).ok_or_else(|| TeangaError::IndexOutOfRange(name.to_string(), i))
+}
+
+/// Check that following `on` from a layer reaches a character layer without
+/// returning to the layer
+fn check_acyclic(name : &str, meta : &HashMap<String, LayerDesc>) -> TeangaResult<()> {
+    let mut seen = HashSet::new();
+    let mut on = name;
+    while let Some(desc) = meta.get(on) {
+        if desc.layer_type == LayerType::Characters {
+            break;
+        }
+        if !seen.insert(on) {
+            return Err(TeangaError::CyclicLayer(name.to_string()));
+        }
+        on = &desc.on;
+    }
+    Ok(())
+}
+
+/// Compute the Teanga identifier of a document. This is the shortest prefix,
+/// of at least four characters, of the base64-encoded SHA-256 hash of the
+/// document's character layers that is not one of `existing_ids`, and is the
+/// same identifier that the Python Teanga library generates.
+pub fn teanga_id(existing_ids : &HashSet<String>, doc : &Document) -> String {
+    let code = teanga_code(doc);
+    let mut n = 4;
+    while n < code.len() && existing_ids.contains(&code[..n]) {
+        n += 1;
+    }
+    code[..n].to_string()
+}
+
+/// The full base64-encoded hash from which the Teanga identifier of a
+/// document is taken
+fn teanga_code(doc : &Document) -> String {
+    let mut hasher = Sha256::new();
+    let text_layers = doc.get_text_layers();
+    let mut names : Vec<&String> = text_layers.keys().collect();
+    names.sort();
+    for name in names {
+        hasher.update(name.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(text_layers[name].as_bytes());
+        hasher.update([0u8]);
+    }
+    base64::engine::general_purpose::STANDARD.encode(hasher.finalize())
+}
+
+/// The layers of a corpus in a canonical order: each character layer
+/// followed by the layers that are on it, depth first and by name. Layers
+/// that are not on a character layer come last, by name.
+pub fn layer_order(meta : &HashMap<String, LayerDesc>) -> Vec<&String> {
+    let mut order : Vec<&String> = LayerTree::from_meta(meta).names().into_iter()
+        .filter_map(|name| meta.get_key_value(name).map(|(name, _)| name))
+        .collect();
+    let in_tree : HashSet<&String> = order.iter().copied().collect();
+    let mut rest : Vec<&String> = meta.keys().filter(|name| !in_tree.contains(name)).collect();
+    rest.sort();
+    order.extend(rest);
+    order
+}
+
 #[derive(Debug,Clone,PartialEq)]
 struct LayerTree {
     data : HashMap<String, LayerTree>
@@ -368,103 +530,95 @@ impl LayerTree {
        build_data("", &all_data)
     }
     
-    pub fn contains(&self, a : &str) -> bool {
-        self.data.contains_key(a) || self.data.values().any(|v| v.contains(a))
-    }
-
-    pub fn cmp_str(&self, a : &str, b: &str) -> Ordering {
-        if self.data.contains_key(a) && self.data.values().any(|v| v.contains(b)) {
-            Ordering::Less
-        } else if self.data.contains_key(b) && self.data.values().any(|v| v.contains(a)) {
-            Ordering::Greater
-        } else {
-            for (_,v) in self.data.iter() {
-                if v.contains(a) && v.contains(b) {
-                    return v.cmp_str(a,b);
-                }
-            }
-            a.cmp(b)
-        }
+    /// The layers in the tree, each followed by the layers that are on it,
+    /// with the layers at each level ordered by name
+    pub fn names(&self) -> Vec<&str> {
+        let mut keys : Vec<&String> = self.data.keys().collect();
+        keys.sort();
+        keys.into_iter().flat_map(|key| std::iter::once(key.as_str()).chain(self.data[key].names())).collect()
     }
-}
 
-fn merge_annos_recursively<'a,'b>(annos : Vec<Anno<'a, 'b>>) -> Vec<Anno<'a,'b>> {
-    let mut new_annos = Vec::new();
-    let mut span_i = 0;
-    let mut span_j = 0;
-    let mut batch = Vec::new();
-    let mut batch_anno : Option<Anno<'a,'b>> = None;
-    for anno in annos {
-        // Anno is in overlap with the current batch
-        if span_j != 0 && anno.start <= span_j && anno.end >= span_i {
-            batch.push(anno);
-        } else {
-            if let Some(mut batch_anno) = batch_anno {
-                batch_anno.children = merge_annos_recursively(batch);
-                new_annos.push(batch_anno);
+    /// The position of each layer when layers are ordered by their depth in
+    /// the tree and then by name, so that a layer comes before the layers on it
+    pub fn ranks(&self) -> HashMap<&str, usize> {
+        fn collect<'a>(tree : &'a LayerTree, depth : usize, layers : &mut Vec<(usize, &'a str)>) {
+            for (name, subtree) in tree.data.iter() {
+                layers.push((depth, name));
+                collect(subtree, depth + 1, layers);
             }
-            batch = Vec::new();
-            span_i = anno.start;
-            span_j = anno.end;
-            batch_anno = Some(anno);
         }
+        let mut layers = Vec::new();
+        collect(self, 0, &mut layers);
+        layers.sort();
+        layers.into_iter().enumerate().map(|(i, (_, name))| (name, i)).collect()
     }
-    if let Some(mut batch_anno) = batch_anno {
-        batch_anno.children = merge_annos_recursively(batch);
-        new_annos.push(batch_anno);
-    }
-    new_annos
 }
 
-fn calc_divisions<'a,'b>(annos : &Vec<Anno<'a,'b>>) -> Vec<(usize, usize)> {
-    let mut divisions = annos.iter().map(|a| (a.start, a.end)).collect::<Vec<(usize,usize)>>();
-    'outer: loop {
-        // We are looking for overlaps
-        //    i.0     i.1
-        //    -----------
-        //    |         |
-        //    -----------
-        //        -----------
-        //        |         |
-        //        -----------
-        //        j.0     j.1
-        //
-        // and map them to three blocks
-        //
-        //   i.0  j.0  i.1 j.1
-        //   -----------------
-        //   |   |   |   |   |
-        //   -----------------
-        for i in 0..divisions.len() {
-            for j in i+1..divisions.len() {
-                if divisions[j].0 > divisions[i].0 && divisions[j].0 < divisions[i].1 
-                    && divisions[j].1 > divisions[i].1 {
-                        let (i0, i1) = divisions.remove(i);
-                        let (j0, j1) = divisions.remove(j-1);
-                        divisions.push((i0, j0));
-                        divisions.push((j0, j1));
-                        divisions.push((i1, j1));
-                        continue 'outer;
-                }
+/// The order in which annotations are laid out: by start, then longest
+/// first, then by the rank of the layer and finally by index
+type LayoutKey = (usize, Reverse<usize>, usize, usize);
+
+/// Arrange the annotations on a character layer into a tree for display.
+/// The annotations are taken in order of start, then longest first, then by
+/// the rank of their layer, and each is nested in the last annotation that is
+/// still open at its start. An annotation that runs past the end of that
+/// annotation is split there: the first part is nested and the rest is placed
+/// again later, and the parts are marked as not complete where they were split.
+/// So an annotation is only ever split at the end of another annotation, and
+/// annotations that do not cross any other are never split.
+///
+/// This differs from the earlier layout on purpose. An annotation nested in
+/// another keeps its own extent rather than being widened to the outermost
+/// annotation containing it, annotations that only touch are siblings rather
+/// than nested, and of two crossing annotations the later one is split at the
+/// end of the earlier one rather than one of them being dropped.
+///
+/// This sweeps over the starts with a heap of waiting annotations and a stack
+/// of open ones, so it takes O(n log n) time in the number of parts.
+fn layout_annos(annos : Vec<Anno>, ranks : &HashMap<&str, usize>) -> Vec<Anno> {
+    let rank = |anno : &Anno| ranks.get(&*anno.layer_name).copied().unwrap_or(usize::MAX);
+    let mut waiting : BinaryHeap<Reverse<LayoutKey>> = annos.iter().enumerate()
+        .map(|(k, anno)| Reverse((anno.start, Reverse(anno.end), rank(anno), k)))
+        .collect();
+    let mut annos : Vec<Option<Anno>> = annos.into_iter().map(Some).collect();
+    // The parts in the order they are placed, with the index of their parent
+    let mut parts : Vec<(Anno, Option<usize>)> = Vec::new();
+    let mut open : Vec<usize> = Vec::new();
+    while let Some(Reverse((start, Reverse(end), r, k))) = waiting.pop() {
+        let Some(mut anno) = annos[k].take() else { continue };
+        while open.last().is_some_and(|p| parts[*p].0.end <= start) {
+            open.pop();
+        }
+        let parent = open.last().copied();
+        if let Some(p) = parent {
+            let split = parts[p].0.end;
+            if end > split {
+                let mut rest = Anno::new(anno.layer_name.clone(), anno.data.clone(), split, end);
+                rest.index = anno.index;
+                rest.left_complete = false;
+                rest.right_complete = anno.right_complete;
+                anno.end = split;
+                anno.right_complete = false;
+                waiting.push(Reverse((split, Reverse(end), r, annos.len())));
+                annos.push(Some(rest));
             }
         }
-        break;
-    }
-    // Sort by start and the by end in reverse order
-    divisions.sort_by(|a,b| {
-        if a.0 < b.0 {
-            std::cmp::Ordering::Less
-        } else if a.0 > b.0 {
-            std::cmp::Ordering::Greater
-        } else if a.1 < b.1 {
-            std::cmp::Ordering::Greater
-        } else if a.1 > b.1 {
-            std::cmp::Ordering::Less
-        } else {
-            std::cmp::Ordering::Equal
-        }
-    });
-    divisions
+        parts.push((anno, parent));
+        open.push(parts.len() - 1);
+    }
+    // Parents are placed before their children, so build the tree from the end
+    let mut children : Vec<Vec<Anno>> = vec![Vec::new(); parts.len()];
+    let mut roots = Vec::new();
+    while let Some((mut anno, parent)) = parts.pop() {
+        anno.children = std::mem::take(&mut children[parts.len()]);
+        anno.children.reverse();
+        match parent {
+            Some(p) => children[p].push(anno),
+            None => roots.push(anno)
+        }
+    }
+    roots.reverse();
+    roots
 }
 
 #[derive(Debug,Clone, PartialEq)]
@@ -588,43 +742,177 @@ mod test {
         assert_eq!(base[0].end, 4);
         assert_eq!(base[1].start, 5);
         assert_eq!(base[1].end, 7);
+        assert_eq!(base.iter().map(|a| a.index).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
     }
 
     #[test]
-    fn test_calc_divisions() {
+    fn test_layout_disjoint() {
         let corpus = crate::serialization::read_corpus_from_json_string(
             "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\"}},\"_order\":[\"Kjco\"],
 \"Kjco\":{\"text\":\"This is a document.\",\"tokens\":[[0,4],[5,7],[8,9],[10,19]]},
 \"abcd\":{\"text\":\"This is a second document\"}}").unwrap();
         let doc = &corpus.documents[0].1;
         let meta = &corpus.meta;
-        let (base, on) = doc.base_annos("tokens", meta).unwrap();
-        let divisions = calc_divisions(&base);
-        assert_eq!(divisions.len(), 4);
-        assert_eq!(divisions[0].0, 0);
-        assert_eq!(divisions[0].1, 4);
-        assert_eq!(divisions[1].0, 5);
-        assert_eq!(divisions[1].1, 7);
-        assert_eq!(divisions[2].0, 8);
-        assert_eq!(divisions[2].1, 9);
-        assert_eq!(divisions[3].0, 10);
-        assert_eq!(divisions[3].1, 19);
+        let (base, _on) = doc.base_annos("tokens", meta).unwrap();
+        let layout = layout_annos(base.clone(), &LayerTree::from_meta(meta).ranks());
+        assert_eq!(layout, base);
+        assert_eq!(layout.iter().map(|a| (a.start, a.end)).collect::<Vec<_>>(),
+            vec![(0, 4), (5, 7), (8, 9), (10, 19)]);
+        let annos = vec![Anno::new("tokens".into(), None, 0, 4), Anno::new("tokens".into(), None, 5, 7),
+            Anno::new("tokens".into(), None, 9, 10), Anno::new("tokens".into(), None, 11, 19)];
+        assert_eq!(layout_annos(annos.clone(), &HashMap::new()), annos);
+    }
+
+    #[test]
+    fn test_layout_nested() {
+        let corpus = crate::serialization::read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\"},
+\"pos\":{\"type\":\"seq\",\"on\":\"tokens\",\"data\":\"string\"},\"sentences\":{\"type\":\"div\",\"on\":\"text\"}},
+\"Kjco\":{\"text\":\"This is a document.\",\"tokens\":[[0,4],[5,7],[8,9],[10,19]],
+\"pos\":[\"DT\",\"VBZ\",\"DT\",\"NN\"],\"sentences\":[0]}}").unwrap();
+        let (secs, errors) = corpus.documents[0].1.get_annos(&corpus.meta);
+        assert!(errors.is_empty());
+        let annos = &secs["text"].annos;
+        assert_eq!(annos.len(), 1);
+        assert_eq!((&*annos[0].layer_name, annos[0].start, annos[0].end), ("sentences", 0, 19));
+        let tokens = &annos[0].children;
+        assert_eq!(tokens.iter().map(|a| (&*a.layer_name, a.start, a.end)).collect::<Vec<_>>(),
+            vec![("tokens", 0, 4), ("tokens", 5, 7), ("tokens", 8, 9), ("tokens", 10, 19)]);
+        assert!(tokens.iter().all(|a| a.children.len() == 1 && &*a.children[0].layer_name == "pos"
+            && a.children[0].start == a.start && a.children[0].end == a.end));
     }
 
     #[test]
-    fn test_merge_annos_recursively() {
+    fn test_layout_crossing() {
+        let annos = vec![Anno::new("a".into(), None, 0, 10), Anno::new("b".into(), None, 5, 15)];
+        let layout = layout_annos(annos, &HashMap::new());
 
-        let annos = vec![
-            Anno { layer_name: "tokens", data: None, left_complete: true, 
-                right_complete: true, start: 0, end: 4, children: Vec::new() },
-            Anno { layer_name: "tokens", data: None, left_complete: true, 
-                right_complete: true, start: 5, end: 7, children: Vec::new() },
-            Anno { layer_name: "tokens", data: None, left_complete: true, 
-                right_complete: true, start: 9, end: 10, children: Vec::new() },
-            Anno { layer_name: "tokens", data: None, left_complete: true, 
-                right_complete: true, start: 11, end: 19, children: Vec::new() }];
-        let results = merge_annos_recursively(annos.clone());
-        assert_eq!(annos, results);
+        fn parts(annos : &[Anno]) -> Vec<(&str, usize, usize, bool, bool)> {
+            annos.iter().map(|a| (&*a.layer_name, a.start, a.end, a.left_complete, a.right_complete)).collect()
+        }
+        assert_eq!(parts(&layout), vec![("a", 0, 10, true, true), ("b", 10, 15, false, true)]);
+        assert_eq!(parts(&layout[0].children), vec![("b", 5, 10, true, false)]);
+    }
+
+    #[test]
+    fn test_layout_differs_from_quadratic() {
+        // The depth and extent of each part in order
+        fn extents(annos : &[Anno], depth : usize) -> Vec<(usize, usize, usize)> {
+            annos.iter().flat_map(|a| std::iter::once((depth, a.start, a.end))
+                .chain(extents(&a.children, depth + 1))).collect()
+        }
+        // A nested annotation keeps its extent
+        let annos = spans_to_annos(&[(0, 10, 0), (2, 5, 1)]);
+        assert_eq!(extents(&layout_annos(annos.clone(), &test_ranks()), 0), vec![(0, 0, 10), (1, 2, 5)]);
+        assert_eq!(extents(&quadratic::layout(&annos, &test_ranks()), 0), vec![(0, 0, 10), (1, 0, 10)]);
+        // Touching annotations are siblings
+        let annos = spans_to_annos(&[(0, 4, 0), (4, 8, 0)]);
+        assert_eq!(extents(&layout_annos(annos.clone(), &test_ranks()), 0), vec![(0, 0, 4), (0, 4, 8)]);
+        assert_eq!(extents(&quadratic::layout(&annos, &test_ranks()), 0), vec![(0, 0, 4), (1, 4, 8)]);
+        // Only the later of two crossing annotations is split, where the
+        // quadratic layout dropped the earlier one
+        let annos = spans_to_annos(&[(0, 10, 0), (5, 15, 1)]);
+        assert_eq!(extents(&layout_annos(annos.clone(), &test_ranks()), 0), vec![(0, 0, 10), (1, 5, 10), (0, 10, 15)]);
+        assert_eq!(extents(&quadratic::layout(&annos, &test_ranks()), 0), vec![(0, 5, 15)]);
+    }
+
+    #[test]
+    fn test_teanga_id() {
+        let mut doc = Document::new();
+        doc.content.insert("text".to_string(), Layer::Characters("This is a document.".to_string()));
+        let mut ids = HashSet::new();
+        assert_eq!(teanga_id(&ids, &doc), "Kjco");
+        ids.insert("Kjco".to_string());
+        assert_eq!(teanga_id(&ids, &doc), "KjcoZ");
+    }
+
+    #[test]
+    fn test_check_ids() {
+        let mut corpus = crate::serialization::read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"}},\"_order\":[\"Kjco\",\"gone\"],
+\"Kjco\":{\"text\":\"This is a document.\"},
+\"abcd\":{\"text\":\"This is a second document\"}}").unwrap();
+        let problems = corpus.check_ids();
+        assert_eq!(problems.len(), 3);
+        assert!(matches!(&problems[0], IdProblem::WrongId { id, .. } if id == "abcd"));
+        assert_eq!(problems[1], IdProblem::MissingDocument("gone".to_string()));
+        assert_eq!(problems[2], IdProblem::NotInOrder("abcd".to_string()));
+        corpus = Corpus::from_documents(corpus.meta, corpus.documents.into_iter().map(|(_, d)| d).collect());
+        assert_eq!(corpus.check_ids(), Vec::new());
+    }
+
+    #[test]
+    fn test_get_annos_out_of_range() {
+        let corpus = crate::serialization::read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\"},
+\"names\":{\"type\":\"span\",\"on\":\"tokens\"}},\"_order\":[\"Kjco\"],
+\"Kjco\":{\"text\":\"This is a document.\",\"tokens\":[[0,4],[5,7],[8,9],[10,19]],\"names\":[[3,5]]}}").unwrap();
+        let (secs, errors) = corpus.documents[0].1.get_annos(&corpus.meta);
+        assert_eq!(secs["text"].annos.len(), 4);
+        assert!(matches!(errors["names"], TeangaError::IndexOutOfRange(ref layer, 4) if layer == "names"));
+    }
+
+    #[test]
+    fn test_base_annos_cyclic() {
+        let mut corpus = crate::serialization::read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\"}},\"_order\":[\"Kjco\"],
+\"Kjco\":{\"text\":\"This is a document.\",\"tokens\":[[0,4],[5,7],[8,9],[10,19]]}}").unwrap();
+        corpus.meta.get_mut("tokens").unwrap().on = "tokens".to_string();
+        let doc = &corpus.documents[0].1;
+        assert!(matches!(doc.base_annos("tokens", &corpus.meta), Err(TeangaError::CyclicLayer(_))));
+        assert!(matches!(doc.base_annos("pos", &corpus.meta), Err(TeangaError::MissingLayer(_))));
+    }
+
+    #[test]
+    fn test_layer_order() {
+        let corpus = crate::serialization::read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"words\":{\"type\":\"span\",\"on\":\"text\"},
+\"pos\":{\"type\":\"seq\",\"on\":\"words\",\"data\":\"string\"},\"lemma\":{\"type\":\"seq\",\"on\":\"words\",\"data\":\"string\"},
+\"sentences\":{\"type\":\"div\",\"on\":\"text\"},\"orphan\":{\"type\":\"div\",\"on\":\"missing\"},
+\"abstract\":{\"type\":\"characters\"}}}").unwrap();
+        assert_eq!(layer_order(&corpus.meta), vec!["abstract", "text", "sentences", "words", "lemma", "pos", "orphan"]);
+    }
+
+    #[test]
+    fn test_get_annos_multilingual() {
+        let corpus = crate::serialization::read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"sentences\":{\"type\":\"div\",\"on\":\"text\"},
+\"chars\":{\"type\":\"seq\",\"on\":\"text\",\"data\":\"string\"}},
+\"KpDf\":{\"text\":\"Dia duit. 你好。👋🏽\",\"sentences\":[0,10,13],
+\"chars\":[\"a\",\"b\",\"c\",\"d\",\"e\",\"f\",\"g\",\"h\",\"i\",\"j\",\"k\",\"l\",\"m\",\"n\",\"o\"]}}").unwrap();
+        let doc = &corpus.documents[0].1;
+        let (base, _) = doc.base_annos("sentences", &corpus.meta).unwrap();
+        assert_eq!(base.iter().map(|a| (a.start, a.end)).collect::<Vec<_>>(), vec![(0, 10), (10, 13), (13, 15)]);
+        let (base, _) = doc.base_annos("chars", &corpus.meta).unwrap();
+        assert_eq!(base.len(), 15);
+        let (_, errors) = doc.get_annos(&corpus.meta);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_seq_on_characters() {
+        let corpus = crate::serialization::read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"},
+\"seg\":{\"type\":\"seq\",\"on\":\"text\",\"data\":[\"B\",\"I\"]}},
+\"KpDf\":{\"text\":\"我爱北京\",\"seg\":[\"B\",\"B\",\"B\",\"I\"]}}").unwrap();
+        let doc = &corpus.documents[0].1;
+        let (base, on) = doc.base_annos("seg", &corpus.meta).unwrap();
+        assert_eq!(on, "text");
+        assert_eq!(base.iter().map(|a| (a.start, a.end, a.data.clone())).collect::<Vec<_>>(), vec![
+            (0, 1, Some(Data::String("B".to_string()))),
+            (1, 2, Some(Data::String("B".to_string()))),
+            (2, 3, Some(Data::String("B".to_string()))),
+            (3, 4, Some(Data::String("I".to_string())))]);
+    }
+
+    #[test]
+    fn test_doc_order() {
+        let mut corpus = Corpus::new();
+        for id in ["a", "b", "c", "d"] {
+            corpus.documents.push((id.to_string(), Document::new()));
+        }
+        corpus.order = vec!["c".to_string(), "x".to_string(), "a".to_string(), "c".to_string()];
+        assert_eq!(corpus.doc_order(), vec![2, 0, 1, 3]);
     }
 
     #[test]
@@ -656,7 +944,241 @@ mod test {
         });
         let layer_tree = LayerTree::from_meta(&meta);
         eprintln!("{:?}", layer_tree);
-        assert!(layer_tree.contains("text"));
-        assert_eq!(layer_tree.cmp_str("tokens", "pos"), Ordering::Less);
+        assert!(layer_tree.names().contains(&"text"));
+        let ranks = layer_tree.ranks();
+        assert!(ranks["text"] < ranks["tokens"]);
+        assert!(ranks["tokens"] < ranks["pos"]);
+    }
+
+    /// The layout before `layout_annos`, which split crossing annotations
+    /// with a quadratic search, widened each annotation to the first division
+    /// containing it and then nested overlapping annotations
+    mod quadratic {
+        use super::super::*;
+
+        fn calc_divisions(annos : &[Anno]) -> Vec<(usize, usize)> {
+            let mut divisions = annos.iter().map(|a| (a.start, a.end)).collect::<Vec<(usize,usize)>>();
+            'outer: loop {
+                for i in 0..divisions.len() {
+                    for j in i+1..divisions.len() {
+                        if divisions[j].0 > divisions[i].0 && divisions[j].0 < divisions[i].1
+                            && divisions[j].1 > divisions[i].1 {
+                                let (i0, i1) = divisions.remove(i);
+                                let (j0, j1) = divisions.remove(j-1);
+                                divisions.push((i0, j0));
+                                divisions.push((j0, j1));
+                                divisions.push((i1, j1));
+                                continue 'outer;
+                        }
+                    }
+                }
+                break;
+            }
+            divisions.sort_by(|a,b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+            divisions
+        }
+
+        fn merge_annos_recursively(annos : Vec<Anno>) -> Vec<Anno> {
+            let mut new_annos = Vec::new();
+            let mut span_i = 0;
+            let mut span_j = 0;
+            let mut batch = Vec::new();
+            let mut batch_anno : Option<Anno> = None;
+            for anno in annos {
+                if span_j != 0 && anno.start <= span_j && anno.end >= span_i {
+                    batch.push(anno);
+                } else {
+                    if let Some(mut batch_anno) = batch_anno {
+                        batch_anno.children = merge_annos_recursively(batch);
+                        new_annos.push(batch_anno);
+                    }
+                    batch = Vec::new();
+                    span_i = anno.start;
+                    span_j = anno.end;
+                    batch_anno = Some(anno);
+                }
+            }
+            if let Some(mut batch_anno) = batch_anno {
+                batch_anno.children = merge_annos_recursively(batch);
+                new_annos.push(batch_anno);
+            }
+            new_annos
+        }
+
+        pub fn layout(base_annos : &[Anno], ranks : &HashMap<&str, usize>) -> Vec<Anno> {
+            let divisions = calc_divisions(base_annos);
+            let mut annos2 = Vec::new();
+            for anno in base_annos.iter() {
+                if let Some((i, j)) = divisions.iter().find(|(i, j)| anno.start >= *i && anno.end <= *j) {
+                    let mut anno2 = Anno::new(anno.layer_name.clone(), anno.data.clone(), *i, *j);
+                    anno2.index = anno.index;
+                    anno2.left_complete = anno.start == *i;
+                    anno2.right_complete = anno.end == *j;
+                    annos2.push(anno2);
+                }
+            }
+            annos2.sort_by(|a,b| a.start.cmp(&b.start).then(b.end.cmp(&a.end))
+                .then_with(|| ranks[&*a.layer_name].cmp(&ranks[&*b.layer_name])));
+            merge_annos_recursively(annos2)
+        }
+    }
+
+    /// Annotations over `len` characters, one per generated span, each with
+    /// its index as data so that the parts of an annotation can be found
+    fn spans_to_annos(spans : &[(usize, usize, usize)]) -> Vec<Anno> {
+        const LAYERS : [&str; 3] = ["a", "b", "c"];
+        spans.iter().enumerate()
+            .map(|(k, (start, end, layer))| Anno::new(LAYERS[*layer].into(), Some(Data::Link(k)), *start, *end))
+            .collect()
+    }
+
+    fn test_ranks() -> HashMap<&'static str, usize> {
+        HashMap::from([("a", 0), ("b", 1), ("c", 2)])
+    }
+
+    /// Check that every annotation lies in its parent, that siblings are in
+    /// order and do not overlap, and collect the parts of each annotation
+    fn check_tree(annos : &[Anno], start : usize, end : usize,
+        parts : &mut HashMap<usize, Vec<(usize, usize, bool, bool)>>) {
+        let mut last = start;
+        for anno in annos {
+            assert!(anno.start >= last && anno.start <= anno.end && anno.end <= end, "{:?} in {}..{}", anno, start, end);
+            last = anno.end;
+            if let Some(Data::Link(k)) = anno.data {
+                parts.entry(k).or_default().push((anno.start, anno.end, anno.left_complete, anno.right_complete));
+            }
+            check_tree(&anno.children, anno.start, anno.end, parts);
+        }
+    }
+
+    /// Time the layout of synthetic documents of thousands of tokens, with
+    /// sentences over them and entities that cross them, against the
+    /// quadratic layout. Run with
+    /// `cargo test --release bench_layout -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_layout() {
+        // Sentences are in layer "a", tokens in "b" and entities in "c"
+        fn document(n : usize) -> Vec<(usize, usize, usize)> {
+            let mut spans = Vec::new();
+            for i in 0..n {
+                spans.push((i * 5, i * 5 + 4, 1));
+                if i % 20 == 0 {
+                    spans.push((i * 5, (i + 20).min(n) * 5 - 1, 0));
+                }
+                if i % 7 == 3 {
+                    spans.push((i * 5 + 2, (i * 5 + 12).min(n * 5), 2));
+                }
+            }
+            spans
+        }
+        for n in [1000, 2000, 4000, 100000] {
+            let annos = spans_to_annos(&document(n));
+            let start = std::time::Instant::now();
+            let layout = layout_annos(annos.clone(), &test_ranks());
+            let sweep = start.elapsed();
+            let mut parts = HashMap::new();
+            check_tree(&layout, 0, usize::MAX, &mut parts);
+            assert_eq!(parts.len(), annos.len());
+            if n <= 4000 {
+                let start = std::time::Instant::now();
+                quadratic::layout(&annos, &test_ranks());
+                println!("{} annotations: sweep {:?}, quadratic {:?}", annos.len(), sweep, start.elapsed());
+            } else {
+                println!("{} annotations: sweep {:?}", annos.len(), sweep);
+            }
+        }
+    }
+
+    /// The spans that do not cross an earlier span, so that each pair of
+    /// spans is nested, identical, touching or apart
+    fn without_crossing(spans : Vec<(usize, usize, usize)>) -> Vec<(usize, usize, usize)> {
+        let mut kept : Vec<(usize, usize, usize)> = Vec::new();
+        for span in spans {
+            if kept.iter().all(|k| span.1 <= k.0 || span.0 >= k.1
+                || (span.0 >= k.0 && span.1 <= k.1) || (span.0 <= k.0 && span.1 >= k.1)) {
+                kept.push(span);
+            }
+        }
+        kept
+    }
+
+    proptest::proptest! {
+        /// On annotations that are apart from each other or identical, the
+        /// layout is the same as the quadratic layout
+        #[test]
+        fn prop_layout_matches_quadratic(gaps in proptest::collection::vec(
+            (1usize..5, 1usize..5, proptest::collection::vec(0usize..3, 1..3)), 0..40)) {
+            let mut spans = Vec::new();
+            let mut offset = 0;
+            for (gap, len, layers) in gaps {
+                for layer in layers {
+                    spans.push((offset + gap, offset + gap + len, layer));
+                }
+                offset += gap + len;
+            }
+            let annos = spans_to_annos(&spans);
+            proptest::prop_assert_eq!(layout_annos(annos.clone(), &test_ranks()), quadratic::layout(&annos, &test_ranks()));
+        }
+
+        /// Annotations that do not cross are never split, and each is nested
+        /// in all the annotations that contain it and come before it in the
+        /// layout order
+        #[test]
+        fn prop_layout_nests_without_splitting(spans in proptest::collection::vec((0usize..30, 1usize..10, 0usize..3), 0..60)) {
+            fn depths(annos : &[Anno], depth : usize, found : &mut HashMap<usize, usize>) {
+                for anno in annos {
+                    if let Some(Data::Link(k)) = anno.data {
+                        found.insert(k, depth);
+                    }
+                    depths(&anno.children, depth + 1, found);
+                }
+            }
+            let spans = without_crossing(spans.into_iter().map(|(start, len, layer)| (start, start + len, layer)).collect());
+            let ranks = test_ranks();
+            let layout = layout_annos(spans_to_annos(&spans), &ranks);
+            let mut parts = HashMap::new();
+            check_tree(&layout, 0, usize::MAX, &mut parts);
+            let mut found = HashMap::new();
+            depths(&layout, 0, &mut found);
+            let key = |k : usize| {
+                let (start, end, layer) = spans[k];
+                (start, Reverse(end), ranks[["a", "b", "c"][layer]], k)
+            };
+            for (k, (start, end, _)) in spans.iter().enumerate() {
+                proptest::prop_assert_eq!(&parts[&k], &vec![(*start, *end, true, true)]);
+                let containing = spans.iter().enumerate()
+                    .filter(|(j, (i, e, _))| i <= start && end <= e && key(*j) < key(k))
+                    .count();
+                proptest::prop_assert_eq!(found[&k], containing);
+            }
+        }
+
+        /// Every annotation is laid out as parts that cover it exactly, with
+        /// only its first part complete on the left and its last on the right,
+        /// and it is only split at the end of another annotation
+        #[test]
+        fn prop_layout_covers_annotations(spans in proptest::collection::vec((0usize..30, 1usize..10, 0usize..3), 0..60)) {
+            let spans : Vec<(usize, usize, usize)> = spans.into_iter().map(|(start, len, layer)| (start, start + len, layer)).collect();
+            let layout = layout_annos(spans_to_annos(&spans), &test_ranks());
+            let mut parts = HashMap::new();
+            check_tree(&layout, 0, usize::MAX, &mut parts);
+            for (k, (start, end, _)) in spans.iter().enumerate() {
+                let mut parts = parts.remove(&k).unwrap_or_default();
+                parts.sort();
+                proptest::prop_assert!(!parts.is_empty());
+                proptest::prop_assert_eq!(parts[0].0, *start);
+                proptest::prop_assert_eq!(parts[parts.len() - 1].1, *end);
+                for (n, (i, j, left, right)) in parts.iter().enumerate() {
+                    proptest::prop_assert!(i < j);
+                    proptest::prop_assert_eq!(*left, n == 0);
+                    proptest::prop_assert_eq!(*right, n == parts.len() - 1);
+                    if n > 0 {
+                        proptest::prop_assert_eq!(parts[n - 1].1, *i);
+                        proptest::prop_assert!(spans.iter().any(|(s, e, _)| s < i && e == i));
+                    }
+                }
+            }
+        }
     }
 }
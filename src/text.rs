@@ -0,0 +1,96 @@
+//! Creating Teanga corpora from plain text
+use std::collections::{HashMap, HashSet};
+use unicode_segmentation::UnicodeSegmentation;
+use crate::teanga::{Corpus, Document, Layer, LayerDesc, LayerType, TEXT_LAYER};
+
+/// The names of the layers created from plain text, besides the text
+pub const TOKENS_LAYER : &str = "tokens";
+pub const SENTENCES_LAYER : &str = "sentences";
+
+/// Split a text into tokens at Unicode word boundaries (UAX #29), dropping
+/// whitespace. The spans are in characters, not bytes.
+pub fn tokenize(text : &str) -> Vec<(usize, usize)> {
+    let mut tokens = Vec::new();
+    let mut chars = 0;
+    for word in text.split_word_bounds() {
+        let len = word.chars().count();
+        if !word.chars().all(char::is_whitespace) {
+            tokens.push((chars, chars + len));
+        }
+        chars += len;
+    }
+    tokens
+}
+
+/// Split a text into sentences at Unicode sentence boundaries (UAX #29),
+/// returning the index of the first token of each sentence
+pub fn split_sentences(text : &str, tokens : &[(usize, usize)]) -> Vec<usize> {
+    let mut sentences = Vec::new();
+    let mut chars = 0;
+    let mut token = 0;
+    for sentence in text.split_sentence_bounds() {
+        let end = chars + sentence.chars().count();
+        if token < tokens.len() && tokens[token].0 < end {
+            sentences.push(token);
+            while token < tokens.len() && tokens[token].0 < end {
+                token += 1;
+            }
+        }
+        chars = end;
+    }
+    sentences
+}
+
+/// Create a corpus with one document for each non-empty text. Each
+/// document has the text, a span layer of tokens and a division layer of
+/// sentences over the tokens, and is named with its Teanga identifier.
+pub fn read_corpus_from_texts<S : AsRef<str>>(texts : &[S]) -> Corpus {
+    let mut docs = Vec::new();
+    for text in texts.iter().map(|t| t.as_ref()).filter(|t| !t.trim().is_empty()) {
+        let tokens = tokenize(text);
+        let sentences = split_sentences(text, &tokens);
+        let mut content = HashMap::new();
+        content.insert(TEXT_LAYER.to_string(), Layer::Characters(text.to_string()));
+        content.insert(TOKENS_LAYER.to_string(), Layer::SpanNoData(tokens));
+        content.insert(SENTENCES_LAYER.to_string(), Layer::DivNoData(sentences));
+        docs.push(Document { content, defaults: HashSet::new() });
+    }
+    Corpus::from_documents(text_meta(), docs)
+}
+
+fn text_meta() -> HashMap<String, LayerDesc> {
+    let mut meta = HashMap::new();
+    meta.insert(TEXT_LAYER.to_string(), LayerDesc::new(LayerType::Characters, "", None));
+    meta.insert(TOKENS_LAYER.to_string(), LayerDesc::new(LayerType::Span, TEXT_LAYER, None));
+    meta.insert(SENTENCES_LAYER.to_string(), LayerDesc::new(LayerType::Div, TOKENS_LAYER, None));
+    meta
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(tokenize("Dia dhuit, a chara! Conas atá tú?"), vec![
+            (0, 3), (4, 9), (9, 10), (11, 12), (13, 18), (18, 19),
+            (20, 25), (26, 29), (30, 32), (32, 33)]);
+        // Without a dictionary, UAX #29 splits ideographs into single characters
+        assert_eq!(tokenize("東京は大きい"), vec![(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 6)]);
+    }
+
+    #[test]
+    fn test_split_sentences() {
+        let text = "Dia dhuit, a chara! Conas atá tú?";
+        assert_eq!(split_sentences(text, &tokenize(text)), vec![0, 6]);
+    }
+
+    #[test]
+    fn test_read_corpus_from_texts() {
+        let corpus = read_corpus_from_texts(&["This is a document.", "  ", "Another one. Two sentences."]);
+        assert_eq!(corpus.order.len(), 2);
+        assert_eq!(corpus.order[0], "Kjco");
+        let doc = &corpus.documents[1].1;
+        assert_eq!(doc.content[SENTENCES_LAYER], Layer::DivNoData(vec![0, 3]));
+    }
+}
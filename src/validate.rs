@@ -0,0 +1,394 @@
+//! Validation of a corpus against the layer descriptions in its metadata
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
+use crate::offsets::{char_len, grapheme_boundaries};
+use crate::teanga::{Corpus, Data, DataType, Document, Layer, LayerDesc, LayerType};
+
+#[derive(Debug,Clone,PartialEq)]
+/// A problem found while validating a corpus, with its location
+pub struct Diagnostic {
+    /// The document, or `None` if the problem is in `_meta`
+    pub document: Option<String>,
+    pub layer: String,
+    /// The index of the annotation within the layer
+    pub index: Option<usize>,
+    pub problem: Problem,
+}
+
+#[derive(Debug,Clone,PartialEq)]
+/// The kinds of problem that validation reports
+pub enum Problem {
+    /// The layer is based on a layer that is not described in `_meta`
+    UnknownBase(String),
+    /// Following `on` from the layer leads back to the layer
+    CyclicBase,
+    /// The link target of the layer is not described in `_meta`
+    UnknownTarget(String),
+    /// The layer is in the document but not described in `_meta`
+    MissingMeta,
+    /// The layer is in the document but the layer it is based on is not
+    MissingBase(String),
+    /// The layer does not have the form its type and data require
+    WrongLayerType(String),
+    /// A seq layer does not have one value for each item of its base
+    WrongLength { expected: usize, found: usize },
+    /// An index is beyond the end of the base layer
+    OutOfRange { index: usize, len: usize },
+    /// A span does not end after it starts
+    EmptySpan { start: usize, end: usize },
+    /// A division does not start after the previous one
+    UnsortedDivision { start: usize, previous: usize },
+    /// The value of the annotation is not of the data type of the layer
+    WrongData(String),
+    /// The value is not one of the values of the enum
+    UnknownValue(String),
+    /// A link points beyond the end of its target layer
+    BadLink { target: String, index: usize },
+    /// The annotation starts or ends inside a grapheme cluster of the text,
+    /// such as between a letter and its combining accent
+    SplitsGrapheme { offset: usize },
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::UnknownBase(on) => write!(f, "Layer is on {}, which is not in the metadata", on),
+            Problem::CyclicBase => write!(f, "Layer is (indirectly) on itself"),
+            Problem::UnknownTarget(target) => write!(f, "Links target {}, which is not in the metadata", target),
+            Problem::MissingMeta => write!(f, "Layer is not in the metadata"),
+            Problem::MissingBase(on) => write!(f, "Layer is on {}, which is not in the document", on),
+            Problem::WrongLayerType(expected) => write!(f, "Layer should be {}", expected),
+            Problem::WrongLength { expected, found } =>
+                write!(f, "Expected {} values but found {}", expected, found),
+            Problem::OutOfRange { index, len } =>
+                write!(f, "Index {} is out of range for a base of length {}", index, len),
+            Problem::EmptySpan { start, end } => write!(f, "Span [{}, {}] does not end after it starts", start, end),
+            Problem::UnsortedDivision { start, previous } =>
+                write!(f, "Division at {} does not come after the division at {}", start, previous),
+            Problem::WrongData(expected) => write!(f, "Value should be {}", expected),
+            Problem::UnknownValue(value) => write!(f, "{} is not one of the values of the layer", value),
+            Problem::BadLink { target, index } => write!(f, "Link to {} is out of range for {}", index, target),
+            Problem::SplitsGrapheme { offset } =>
+                write!(f, "Annotation is misaligned: character {} is inside a grapheme cluster", offset),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match (&self.document, self.index) {
+            (Some(doc), Some(i)) => write!(f, "{}/{}[{}]: {}", doc, self.layer, i, self.problem),
+            (Some(doc), None) => write!(f, "{}/{}: {}", doc, self.layer, self.problem),
+            (None, _) => write!(f, "_meta/{}: {}", self.layer, self.problem),
+        }
+    }
+}
+
+impl Corpus {
+    /// Check every layer of every document against its description in the
+    /// metadata
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = validate_meta(self);
+        for (id, doc) in self.documents.iter() {
+            let mut names : Vec<&String> = doc.content.keys().collect();
+            names.sort();
+            for name in names {
+                let mut report = |index, problem| diagnostics.push(Diagnostic {
+                    document: Some(id.clone()),
+                    layer: name.clone(),
+                    index,
+                    problem
+                });
+                match self.meta.get(name) {
+                    Some(desc) => {
+                        validate_layer(doc, name, desc, &mut report);
+                        validate_graphemes(self, doc, name, &mut report);
+                    },
+                    None => report(None, Problem::MissingMeta)
+                }
+            }
+        }
+        diagnostics
+    }
+}
+
+/// Check that the `on` and `target` of each layer description exist and that
+/// the layers do not depend on themselves
+fn validate_meta(corpus : &Corpus) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut names : Vec<&String> = corpus.meta.keys().collect();
+    names.sort();
+    for name in names {
+        let desc = &corpus.meta[name];
+        let mut report = |problem| diagnostics.push(Diagnostic {
+            document: None,
+            layer: name.clone(),
+            index: None,
+            problem
+        });
+        if desc.layer_type != LayerType::Characters {
+            let mut seen = HashSet::new();
+            let mut on = &desc.on;
+            while let Some(base) = corpus.meta.get(on) {
+                if on == name {
+                    report(Problem::CyclicBase);
+                    break;
+                }
+                if base.layer_type == LayerType::Characters || !seen.insert(on) {
+                    break;
+                }
+                on = &base.on;
+            }
+            if !corpus.meta.contains_key(&desc.on) {
+                report(Problem::UnknownBase(desc.on.clone()));
+            }
+        }
+        if let Some(target) = &desc.target {
+            if !corpus.meta.contains_key(target) {
+                report(Problem::UnknownTarget(target.clone()));
+            }
+        }
+    }
+    diagnostics
+}
+
+/// The number of items in a layer that other layers may index
+fn layer_len(layer : &Layer) -> usize {
+    match layer {
+        Layer::Characters(s) => char_len(s),
+        Layer::Seq(data) => data.len(),
+        Layer::Div(data) => data.len(),
+        Layer::DivNoData(data) => data.len(),
+        Layer::Element(data) => data.len(),
+        Layer::ElementNoData(data) => data.len(),
+        Layer::Span(data) => data.len(),
+        Layer::SpanNoData(data) => data.len(),
+    }
+}
+
+fn validate_layer(doc : &Document, name : &str, desc : &LayerDesc,
+    report : &mut impl FnMut(Option<usize>, Problem)) {
+    let layer = &doc.content[name];
+    let expected = match (&desc.layer_type, &desc.data) {
+        (LayerType::Characters, _) => matches!(layer, Layer::Characters(_)),
+        (LayerType::Seq, _) => matches!(layer, Layer::Seq(_)),
+        (LayerType::Div, Some(_)) => matches!(layer, Layer::Div(_)),
+        (LayerType::Div, None) => matches!(layer, Layer::DivNoData(_)),
+        (LayerType::Element, Some(_)) => matches!(layer, Layer::Element(_)),
+        (LayerType::Element, None) => matches!(layer, Layer::ElementNoData(_)),
+        (LayerType::Span, Some(_)) => matches!(layer, Layer::Span(_)),
+        (LayerType::Span, None) => matches!(layer, Layer::SpanNoData(_)),
+    };
+    if !expected {
+        report(None, Problem::WrongLayerType(match &desc.data {
+            Some(data) => format!("{} with {} data", desc.layer_type, data),
+            None => format!("{} without data", desc.layer_type)
+        }));
+        return;
+    }
+    if let Layer::Characters(_) = layer {
+        return;
+    }
+    let len = match doc.content.get(&desc.on) {
+        Some(base) => layer_len(base),
+        None => {
+            report(None, Problem::MissingBase(desc.on.clone()));
+            return;
+        }
+    };
+    let out_of_range = |i : usize| (i >= len).then_some(Problem::OutOfRange { index: i, len });
+    match layer {
+        Layer::Characters(_) => {},
+        Layer::Seq(data) => {
+            if data.len() != len {
+                report(None, Problem::WrongLength { expected: len, found: data.len() });
+            }
+        },
+        Layer::Div(_) | Layer::DivNoData(_) => {
+            let starts : Vec<usize> = match layer {
+                Layer::Div(data) => data.iter().map(|(i, _)| *i).collect(),
+                Layer::DivNoData(data) => data.clone(),
+                _ => Vec::new()
+            };
+            let mut previous = None;
+            for (index, start) in starts.into_iter().enumerate() {
+                if let Some(problem) = out_of_range(start) {
+                    report(Some(index), problem);
+                }
+                if let Some(previous) = previous.filter(|p| *p >= start) {
+                    report(Some(index), Problem::UnsortedDivision { start, previous });
+                }
+                previous = Some(start);
+            }
+        },
+        Layer::Element(data) => {
+            for (index, (i, _)) in data.iter().enumerate() {
+                if let Some(problem) = out_of_range(*i) {
+                    report(Some(index), problem);
+                }
+            }
+        },
+        Layer::ElementNoData(data) => {
+            for (index, i) in data.iter().enumerate() {
+                if let Some(problem) = out_of_range(*i) {
+                    report(Some(index), problem);
+                }
+            }
+        },
+        Layer::Span(_) | Layer::SpanNoData(_) => {
+            let spans : Vec<(usize, usize)> = match layer {
+                Layer::Span(data) => data.iter().map(|(i, j, _)| (*i, *j)).collect(),
+                Layer::SpanNoData(data) => data.clone(),
+                _ => Vec::new()
+            };
+            for (index, (start, end)) in spans.into_iter().enumerate() {
+                if start >= end {
+                    report(Some(index), Problem::EmptySpan { start, end });
+                } else if let Some(problem) = out_of_range(end - 1) {
+                    report(Some(index), problem);
+                }
+            }
+        }
+    }
+    if let Some(data_type) = &desc.data {
+        let target = desc.target.as_deref().unwrap_or(&desc.on);
+        let target_len = doc.content.get(target).map(layer_len);
+        for (index, data) in layer_data(layer).into_iter().enumerate() {
+            if let Some(problem) = validate_data(data, data_type, target, target_len) {
+                report(Some(index), problem);
+            }
+        }
+    }
+}
+
+/// Check that each annotation of a layer starts and ends between grapheme
+/// clusters of its text. Seq layers on characters annotate single code
+/// points by design and are not checked.
+fn validate_graphemes(corpus : &Corpus, doc : &Document, name : &str,
+    report : &mut impl FnMut(Option<usize>, Problem)) {
+    let Ok((annos, on)) = doc.base_annos(name, &corpus.meta) else {
+        return;
+    };
+    let Some(Layer::Characters(text)) = doc.content.get(on) else {
+        return;
+    };
+    if matches!(doc.content[name], Layer::Seq(_)) && corpus.meta[name].on == on {
+        return;
+    }
+    let boundaries = grapheme_boundaries(text);
+    let len = char_len(text);
+    for (index, anno) in annos.iter().enumerate() {
+        let split = [anno.start, anno.end].into_iter()
+            .find(|offset| *offset <= len && boundaries.binary_search(offset).is_err());
+        if let Some(offset) = split {
+            report(Some(index), Problem::SplitsGrapheme { offset });
+        }
+    }
+}
+
+/// The data values of a layer in order
+fn layer_data(layer : &Layer) -> Vec<&Data> {
+    match layer {
+        Layer::Seq(data) => data.iter().collect(),
+        Layer::Div(data) => data.iter().map(|(_, d)| d).collect(),
+        Layer::Element(data) => data.iter().map(|(_, d)| d).collect(),
+        Layer::Span(data) => data.iter().map(|(_, _, d)| d).collect(),
+        _ => Vec::new()
+    }
+}
+
+fn validate_data(data : &Data, data_type : &DataType, target : &str, target_len : Option<usize>) -> Option<Problem> {
+    let check_link = |i : usize| match target_len {
+        Some(len) if i < len => None,
+        _ => Some(Problem::BadLink { target: target.to_string(), index: i })
+    };
+    match (data_type, data) {
+        (DataType::String, Data::String(_)) => None,
+        (DataType::Enum(values), Data::String(s)) => if values.contains(s) {
+            None
+        } else {
+            Some(Problem::UnknownValue(s.clone()))
+        },
+        (DataType::Link, Data::Link(i)) => check_link(*i),
+        (DataType::TypedLink(values), Data::TypedLink(i, s)) => if values.contains(s) {
+            check_link(*i)
+        } else {
+            Some(Problem::UnknownValue(s.clone()))
+        },
+        (DataType::Enum(_), _) => Some(Problem::WrongData("one of the values of the enum".to_string())),
+        (data_type, _) => Some(Problem::WrongData(format!("a {}", data_type))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::read_corpus_from_json_string;
+
+    #[test]
+    fn test_validate_valid() {
+        let corpus = read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\"},
+\"pos\":{\"type\":\"seq\",\"on\":\"tokens\",\"data\":[\"DT\",\"VBZ\",\"NN\"]},
+\"sentences\":{\"type\":\"div\",\"on\":\"tokens\"}},\"_order\":[\"Kjco\"],
+\"Kjco\":{\"text\":\"This is a document.\",\"tokens\":[[0,4],[5,7],[8,9],[10,19]],
+\"pos\":[\"DT\",\"VBZ\",\"DT\",\"NN\"],\"sentences\":[0]}}").unwrap();
+        assert_eq!(corpus.validate(), Vec::new());
+    }
+
+    #[test]
+    fn test_validate_errors() {
+        let mut corpus = read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\"},
+\"pos\":{\"type\":\"seq\",\"on\":\"tokens\",\"data\":[\"DT\",\"VBZ\",\"NN\"]},
+\"sentences\":{\"type\":\"div\",\"on\":\"tokens\"},
+\"loop\":{\"type\":\"element\",\"on\":\"loop\"}},\"_order\":[\"Kjco\"],
+\"Kjco\":{\"text\":\"This is a document.\",\"tokens\":[[0,4],[5,7],[9,8],[10,20]],
+\"pos\":[\"DT\",\"VBZ\",\"DT\",\"JJ\"],\"sentences\":[2,1]}}").unwrap();
+        corpus.documents[0].1.content.insert("extra".to_string(), Layer::ElementNoData(vec![1]));
+        let diagnostics = corpus.validate();
+        let found : Vec<(Option<&str>, &str, Option<usize>, &Problem)> = diagnostics.iter()
+            .map(|d| (d.document.as_deref(), d.layer.as_str(), d.index, &d.problem)).collect();
+        assert_eq!(found, vec![
+            (None, "loop", None, &Problem::CyclicBase),
+            (Some("Kjco"), "extra", None, &Problem::MissingMeta),
+            (Some("Kjco"), "pos", Some(3), &Problem::UnknownValue("JJ".to_string())),
+            (Some("Kjco"), "sentences", Some(1), &Problem::UnsortedDivision { start: 1, previous: 2 }),
+            (Some("Kjco"), "tokens", Some(2), &Problem::EmptySpan { start: 9, end: 8 }),
+            (Some("Kjco"), "tokens", Some(3), &Problem::OutOfRange { index: 19, len: 19 }),
+        ]);
+    }
+
+    #[test]
+    fn test_validate_links() {
+        let corpus = read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\"},
+\"dep\":{\"type\":\"seq\",\"on\":\"tokens\",\"data\":\"link\",\"target\":\"tokens\"}},\"_order\":[\"Kjco\"],
+\"Kjco\":{\"text\":\"This is a document.\",\"tokens\":[[0,4],[5,7],[8,9],[10,19]],
+\"dep\":[1,1,3,4,5]}}").unwrap();
+        let diagnostics = corpus.validate();
+        assert_eq!(diagnostics.len(), 3);
+        assert_eq!(diagnostics[0].problem, Problem::WrongLength { expected: 4, found: 5 });
+        assert_eq!(diagnostics[1].index, Some(3));
+        assert_eq!(diagnostics[1].problem, Problem::BadLink { target: "tokens".to_string(), index: 4 });
+        assert_eq!(diagnostics[2].index, Some(4));
+    }
+
+    #[test]
+    fn test_validate_graphemes() {
+        // The second token ends between "e" and its combining accent and the
+        // third starts inside the skin tone modifier of the emoji
+        let corpus = read_corpus_from_json_string(
+            "{\"_meta\":{\"text\":{\"type\":\"characters\"},\"tokens\":{\"type\":\"span\",\"on\":\"text\"},
+\"chars\":{\"type\":\"seq\",\"on\":\"text\",\"data\":\"string\"}},
+\"Kjco\":{\"text\":\"Cafe\u{301} \u{1F44B}\u{1F3FD}\",\"tokens\":[[0,3],[3,4],[7,8]],
+\"chars\":[\"a\",\"b\",\"c\",\"d\",\"e\",\"f\",\"g\",\"h\"]}}").unwrap();
+        let diagnostics = corpus.validate();
+        let found : Vec<(&str, Option<usize>, &Problem)> = diagnostics.iter()
+            .map(|d| (d.layer.as_str(), d.index, &d.problem)).collect();
+        assert_eq!(found, vec![
+            ("tokens", Some(1), &Problem::SplitsGrapheme { offset: 4 }),
+            ("tokens", Some(2), &Problem::SplitsGrapheme { offset: 7 }),
+        ]);
+    }
+}
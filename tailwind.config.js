@@ -29,5 +29,22 @@ module.exports = {
       "border-indigo-900", "bg-indigo-900", "text-indigo-900", "accent-indigo-900",
       "border-pink-900", "bg-pink-900", "text-pink-900", "accent-pink-900",
       "border-rose-900", "bg-rose-900", "text-rose-900", "accent-rose-900",
+      "border-red-500", "bg-red-500",
+      "border-lime-500", "bg-lime-500",
+      "border-cyan-500", "bg-cyan-500",
+      "border-violet-500", "bg-violet-500",
+      "border-orange-500", "bg-orange-500",
+      "border-green-500", "bg-green-500",
+      "border-sky-500", "bg-sky-500",
+      "border-purple-500", "bg-purple-500",
+      "border-amber-500", "bg-amber-500",
+      "border-emerald-500", "bg-emerald-500",
+      "border-blue-500", "bg-blue-500",
+      "border-fuchsia-500", "bg-fuchsia-500",
+      "border-yellow-500", "bg-yellow-500",
+      "border-teal-500", "bg-teal-500",
+      "border-indigo-500", "bg-indigo-500",
+      "border-pink-500", "bg-pink-500",
+      "border-rose-500", "bg-rose-500",
   ],
 };